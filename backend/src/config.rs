@@ -0,0 +1,557 @@
+use std::fs;
+use std::net::SocketAddr;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use clap::{Parser, Subcommand, ValueEnum};
+use fastembed::{EmbeddingModel, Pooling};
+use serde::{Deserialize, Serialize};
+
+use crate::services::embed;
+use crate::services::metric::Metric;
+use crate::services::simple_db_nn::DEFAULT_COLLECTION;
+
+const DEFAULT_LISTEN: &str = "127.0.0.1:3000";
+const DEFAULT_STATIC_DIR: &str = "web/verifier";
+const DEFAULT_MODEL: &str = "Qdrant/all-MiniLM-L6-v2-onnx";
+const DEFAULT_TOP_K: usize = 5;
+const DEFAULT_MAX_TOP_K: usize = 20;
+const DEFAULT_THRESHOLD: f32 = host::DEFAULT_THRESHOLD;
+const DEFAULT_EMBED_BATCH_SIZE: usize = crate::services::embed::DEFAULT_BATCH_SIZE;
+const DEFAULT_PROOF_WORKERS: usize = 2;
+const DEFAULT_PROOF_QUEUE_SIZE: usize = 1024;
+const DEFAULT_MAX_FINISHED_PROOFS: usize = 10_000;
+const DEFAULT_RECEIPT_CACHE_ENTRIES: u64 = 10_000;
+const DEFAULT_RECEIPT_CACHE_BYTES: u64 = 4 * 1024 * 1024 * 1024;
+const DEFAULT_MAX_RANKED_CORPUS: usize = 10_000;
+const DEFAULT_REBUILD_THRESHOLD: usize = crate::services::simple_db_nn::DEFAULT_REBUILD_THRESHOLD;
+const DEFAULT_REBUILD_INTERVAL_SECS: u64 = crate::services::simple_db_nn::DEFAULT_REBUILD_INTERVAL.as_secs();
+const SNAPSHOT_DIR: &str = "snapshots";
+const RECEIPTS_DIR: &str = "receipts";
+
+/// How receipts are produced for search results.
+#[derive(Serialize, Deserialize, ValueEnum, Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum ProvingMode {
+    /// Real proofs with the local prover.
+    #[default]
+    Local,
+    /// RISC0_DEV_MODE: fake receipts, only for development.
+    Dev,
+    /// No receipts at all, searches do not queue proof jobs.
+    None,
+}
+
+/// How the similarity of a search result is computed and proven.
+#[derive(Serialize, Deserialize, ValueEnum, Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum SimilarityMode {
+    /// `f32` cosine similarity, as computed by the index.
+    #[default]
+    Float,
+    /// Integer cosine similarity of `i8` quantized embeddings, reproducible bit for bit
+    /// and cheaper to prove.
+    Fixed,
+}
+
+/// How token embeddings of a local model are pooled into one vector, see the
+/// `1_Pooling/config.json` of the sentence-transformers export.
+#[derive(Serialize, Deserialize, ValueEnum, Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum ModelPooling {
+    #[default]
+    Mean,
+    Cls,
+}
+
+impl From<ModelPooling> for Pooling {
+    fn from(pooling: ModelPooling) -> Self {
+        match pooling {
+            ModelPooling::Mean => Pooling::Mean,
+            ModelPooling::Cls => Pooling::Cls,
+        }
+    }
+}
+
+/// Server settings. Values are layered as defaults < config file < environment < flags.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(default, deny_unknown_fields)]
+pub struct Settings {
+    pub listen: SocketAddr,
+    /// Holds the heed and arroy environments and the snapshots.
+    pub data_dir: PathBuf,
+    /// Served under `/verifier`.
+    pub static_dir: PathBuf,
+    /// fastembed model code, e.g. `Qdrant/all-MiniLM-L6-v2-onnx`. With `model_dir`, a name of
+    /// your own for the local model, fastembed model codes are refused.
+    pub model: String,
+    /// Directory with `model.onnx` and the tokenizer files, loaded instead of downloading `model`.
+    pub model_dir: Option<PathBuf>,
+    /// Pooling of the local model in `model_dir`.
+    pub pooling: ModelPooling,
+    /// Defaults to the dimensions of `model`, required with `model_dir`.
+    pub dimensions: Option<usize>,
+    /// Documents embedded per inference call when importing or batch inserting.
+    pub embed_batch_size: usize,
+    /// Prepended to search queries before embedding, see [`Settings::prefixes`].
+    pub query_prefix: Option<String>,
+    /// Prepended to stored documents before embedding. It is recorded with the model of
+    /// every collection, so changing it requires re-importing the documents.
+    pub passage_prefix: Option<String>,
+    /// Metric of the default collection and of collections created without one.
+    pub metric: Metric,
+    pub default_top_k: usize,
+    pub max_top_k: usize,
+    /// Cosine similarity the guest compares results against when a search gives no `threshold`.
+    pub default_threshold: f32,
+    pub similarity_mode: SimilarityMode,
+    pub proving: ProvingMode,
+    /// Number of proofs generated concurrently.
+    pub proof_workers: usize,
+    /// Jobs waiting for a worker, searches fail once the queue is full.
+    pub proof_queue_size: usize,
+    /// Finished jobs kept for polling, the oldest are forgotten first.
+    pub max_finished_proofs: usize,
+    /// Receipts kept for reuse, 0 disables the receipt store.
+    pub receipt_cache_entries: u64,
+    pub receipt_cache_bytes: u64,
+    /// Largest collection accepted by `/search/ranked`, the ranking proof is brute force.
+    pub max_ranked_corpus: usize,
+    /// Buffered writes that trigger a rebuild of the arroy tree of a collection.
+    pub rebuild_threshold: usize,
+    /// Buffered writes older than this are built in the background, 0 only builds on the threshold.
+    pub rebuild_interval_secs: u64,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Settings {
+            listen: DEFAULT_LISTEN.parse().expect("valid default listen address"),
+            data_dir: PathBuf::from("."),
+            static_dir: PathBuf::from(DEFAULT_STATIC_DIR),
+            model: DEFAULT_MODEL.to_string(),
+            model_dir: None,
+            pooling: ModelPooling::default(),
+            dimensions: None,
+            embed_batch_size: DEFAULT_EMBED_BATCH_SIZE,
+            query_prefix: None,
+            passage_prefix: None,
+            metric: Metric::default(),
+            default_top_k: DEFAULT_TOP_K,
+            max_top_k: DEFAULT_MAX_TOP_K,
+            default_threshold: DEFAULT_THRESHOLD,
+            similarity_mode: SimilarityMode::default(),
+            proving: ProvingMode::default(),
+            proof_workers: DEFAULT_PROOF_WORKERS,
+            proof_queue_size: DEFAULT_PROOF_QUEUE_SIZE,
+            max_finished_proofs: DEFAULT_MAX_FINISHED_PROOFS,
+            receipt_cache_entries: DEFAULT_RECEIPT_CACHE_ENTRIES,
+            receipt_cache_bytes: DEFAULT_RECEIPT_CACHE_BYTES,
+            max_ranked_corpus: DEFAULT_MAX_RANKED_CORPUS,
+            rebuild_threshold: DEFAULT_REBUILD_THRESHOLD,
+            rebuild_interval_secs: DEFAULT_REBUILD_INTERVAL_SECS,
+        }
+    }
+}
+
+#[derive(Parser, Debug)]
+#[command(name = "backend", about = "Document similarity search with zk proofs")]
+pub struct Cli {
+    /// TOML file with the server settings.
+    #[arg(long, env = "DOCSIM_CONFIG")]
+    pub config: Option<PathBuf>,
+    #[arg(long, env = "DOCSIM_LISTEN")]
+    pub listen: Option<SocketAddr>,
+    #[arg(long, env = "DOCSIM_DATA_DIR")]
+    pub data_dir: Option<PathBuf>,
+    #[arg(long, env = "DOCSIM_STATIC_DIR")]
+    pub static_dir: Option<PathBuf>,
+    #[arg(long, env = "DOCSIM_MODEL")]
+    pub model: Option<String>,
+    #[arg(long, env = "DOCSIM_MODEL_DIR")]
+    pub model_dir: Option<PathBuf>,
+    #[arg(long, env = "DOCSIM_POOLING", value_enum)]
+    pub pooling: Option<ModelPooling>,
+    #[arg(long, env = "DOCSIM_DIMENSIONS")]
+    pub dimensions: Option<usize>,
+    #[arg(long, env = "DOCSIM_EMBED_BATCH_SIZE")]
+    pub embed_batch_size: Option<usize>,
+    #[arg(long, env = "DOCSIM_QUERY_PREFIX")]
+    pub query_prefix: Option<String>,
+    #[arg(long, env = "DOCSIM_PASSAGE_PREFIX")]
+    pub passage_prefix: Option<String>,
+    #[arg(long, env = "DOCSIM_METRIC")]
+    pub metric: Option<Metric>,
+    #[arg(long, env = "DOCSIM_DEFAULT_TOP_K")]
+    pub default_top_k: Option<usize>,
+    #[arg(long, env = "DOCSIM_MAX_TOP_K")]
+    pub max_top_k: Option<usize>,
+    #[arg(long, env = "DOCSIM_DEFAULT_THRESHOLD")]
+    pub default_threshold: Option<f32>,
+    #[arg(long, env = "DOCSIM_SIMILARITY_MODE", value_enum)]
+    pub similarity_mode: Option<SimilarityMode>,
+    #[arg(long, env = "DOCSIM_PROVING", value_enum)]
+    pub proving: Option<ProvingMode>,
+    #[arg(long, env = "DOCSIM_PROOF_WORKERS")]
+    pub proof_workers: Option<usize>,
+    #[arg(long, env = "DOCSIM_PROOF_QUEUE_SIZE")]
+    pub proof_queue_size: Option<usize>,
+    #[arg(long, env = "DOCSIM_MAX_FINISHED_PROOFS")]
+    pub max_finished_proofs: Option<usize>,
+    #[arg(long, env = "DOCSIM_RECEIPT_CACHE_ENTRIES")]
+    pub receipt_cache_entries: Option<u64>,
+    #[arg(long, env = "DOCSIM_RECEIPT_CACHE_BYTES")]
+    pub receipt_cache_bytes: Option<u64>,
+    #[arg(long, env = "DOCSIM_MAX_RANKED_CORPUS")]
+    pub max_ranked_corpus: Option<usize>,
+    #[arg(long, env = "DOCSIM_REBUILD_THRESHOLD")]
+    pub rebuild_threshold: Option<usize>,
+    #[arg(long, env = "DOCSIM_REBUILD_INTERVAL_SECS")]
+    pub rebuild_interval_secs: Option<u64>,
+    #[command(subcommand)]
+    pub command: Option<Command>,
+}
+
+/// Offline maintenance commands, run instead of the server.
+#[derive(Subcommand, Debug)]
+pub enum Command {
+    /// Write a snapshot archive of the database.
+    Snapshot { archive: PathBuf },
+    /// Restore a snapshot archive into an empty data dir.
+    Restore { archive: PathBuf },
+    /// Export a collection as JSON lines.
+    Export {
+        file: PathBuf,
+        #[arg(long)]
+        with_embeddings: bool,
+        #[arg(long, default_value = DEFAULT_COLLECTION)]
+        collection: String,
+    },
+    /// Import JSON lines into an existing collection.
+    Import {
+        file: PathBuf,
+        #[arg(long, default_value = DEFAULT_COLLECTION)]
+        collection: String,
+    },
+}
+
+impl Settings {
+    pub fn from_file(path: &Path) -> anyhow::Result<Self> {
+        let raw = fs::read_to_string(path)?;
+        toml::from_str(&raw).map_err(|err| anyhow::anyhow!("Invalid config file {:?}: {}", path, err))
+    }
+
+    /// Reads the config file named by the CLI, if any, and applies the environment and flags on top.
+    pub fn load(cli: &Cli) -> anyhow::Result<Self> {
+        let mut settings = match &cli.config {
+            Some(path) => Settings::from_file(path)?,
+            None => Settings::default(),
+        };
+        settings.merge(cli);
+        settings.validate()?;
+        Ok(settings)
+    }
+
+    fn merge(&mut self, cli: &Cli) {
+        if let Some(listen) = cli.listen {
+            self.listen = listen;
+        }
+        if let Some(data_dir) = &cli.data_dir {
+            self.data_dir = data_dir.clone();
+        }
+        if let Some(static_dir) = &cli.static_dir {
+            self.static_dir = static_dir.clone();
+        }
+        if let Some(model) = &cli.model {
+            self.model = model.clone();
+        }
+        if cli.model_dir.is_some() {
+            self.model_dir = cli.model_dir.clone();
+        }
+        if let Some(pooling) = cli.pooling {
+            self.pooling = pooling;
+        }
+        if cli.dimensions.is_some() {
+            self.dimensions = cli.dimensions;
+        }
+        if let Some(embed_batch_size) = cli.embed_batch_size {
+            self.embed_batch_size = embed_batch_size;
+        }
+        if cli.query_prefix.is_some() {
+            self.query_prefix = cli.query_prefix.clone();
+        }
+        if cli.passage_prefix.is_some() {
+            self.passage_prefix = cli.passage_prefix.clone();
+        }
+        if let Some(metric) = cli.metric {
+            self.metric = metric;
+        }
+        if let Some(default_top_k) = cli.default_top_k {
+            self.default_top_k = default_top_k;
+        }
+        if let Some(max_top_k) = cli.max_top_k {
+            self.max_top_k = max_top_k;
+        }
+        if let Some(default_threshold) = cli.default_threshold {
+            self.default_threshold = default_threshold;
+        }
+        if let Some(similarity_mode) = cli.similarity_mode {
+            self.similarity_mode = similarity_mode;
+        }
+        if let Some(proving) = cli.proving {
+            self.proving = proving;
+        }
+        if let Some(proof_workers) = cli.proof_workers {
+            self.proof_workers = proof_workers;
+        }
+        if let Some(proof_queue_size) = cli.proof_queue_size {
+            self.proof_queue_size = proof_queue_size;
+        }
+        if let Some(max_finished_proofs) = cli.max_finished_proofs {
+            self.max_finished_proofs = max_finished_proofs;
+        }
+        if let Some(receipt_cache_entries) = cli.receipt_cache_entries {
+            self.receipt_cache_entries = receipt_cache_entries;
+        }
+        if let Some(receipt_cache_bytes) = cli.receipt_cache_bytes {
+            self.receipt_cache_bytes = receipt_cache_bytes;
+        }
+        if let Some(max_ranked_corpus) = cli.max_ranked_corpus {
+            self.max_ranked_corpus = max_ranked_corpus;
+        }
+        if let Some(rebuild_threshold) = cli.rebuild_threshold {
+            self.rebuild_threshold = rebuild_threshold;
+        }
+        if let Some(rebuild_interval_secs) = cli.rebuild_interval_secs {
+            self.rebuild_interval_secs = rebuild_interval_secs;
+        }
+    }
+
+    /// Resolves the model and fills in its dimensions, so later code can rely on both.
+    pub fn validate(&mut self) -> anyhow::Result<()> {
+        if let Some(model_dir) = &self.model_dir {
+            if !model_dir.is_dir() {
+                anyhow::bail!("Model dir {:?} does not exist", model_dir);
+            }
+            if self.dimensions.is_none() {
+                anyhow::bail!("dimensions must be configured for the local model in {:?}", model_dir);
+            }
+            // collections record the model by name, a local model must not pass for a downloaded one
+            if self.embedding_model().is_ok() {
+                anyhow::bail!(
+                    "model must name the local model in {:?}, {} is a fastembed model",
+                    model_dir, self.model
+                );
+            }
+        } else {
+            let (_, model_dims) = self.embedding_model()?;
+            match self.dimensions {
+                Some(dimensions) if dimensions != model_dims => anyhow::bail!(
+                    "Model {} produces {} dimensions, but {} are configured",
+                    self.model, model_dims, dimensions
+                ),
+                _ => self.dimensions = Some(model_dims),
+            }
+        }
+        if self.embed_batch_size == 0 {
+            anyhow::bail!("embed_batch_size must be at least 1");
+        }
+        if self.max_top_k == 0 {
+            anyhow::bail!("max_top_k must be at least 1");
+        }
+        if self.default_top_k == 0 || self.default_top_k > self.max_top_k {
+            anyhow::bail!(
+                "default_top_k must be between 1 and max_top_k ({}), got {}",
+                self.max_top_k, self.default_top_k
+            );
+        }
+        if self.threshold(None).is_none() {
+            anyhow::bail!("default_threshold must be between -1 and 1, got {}", self.default_threshold);
+        }
+        if self.proof_workers == 0 || self.proof_queue_size == 0 {
+            anyhow::bail!("proof_workers and proof_queue_size must be at least 1");
+        }
+        if !self.static_dir.is_dir() {
+            tracing::warn!("Static dir {:?} does not exist, /verifier will not be served", self.static_dir);
+        }
+        Ok(())
+    }
+
+    /// The configured fastembed model and its dimensions.
+    pub fn embedding_model(&self) -> anyhow::Result<(EmbeddingModel, usize)> {
+        let model = self.model.parse::<EmbeddingModel>().map_err(anyhow::Error::msg)?;
+        let (_, dims) = embed::model_code(&model)?;
+        Ok((model, dims))
+    }
+
+    /// `(query, passage)` prefixes. E5 models are trained with `query: ` and `passage: `
+    /// and get them unless configured otherwise, other models get none.
+    pub fn prefixes(&self) -> (&str, &str) {
+        let e5 = self.model.to_ascii_lowercase().contains("-e5-");
+        let default = |prefix| if e5 { prefix } else { "" };
+        (
+            self.query_prefix.as_deref().unwrap_or(default("query: ")),
+            self.passage_prefix.as_deref().unwrap_or(default("passage: ")),
+        )
+    }
+
+    pub fn dimensions(&self) -> usize {
+        self.dimensions.expect("dimensions are resolved by validate")
+    }
+
+    /// `None` when buffered writes are only built once `rebuild_threshold` is reached.
+    pub fn rebuild_interval(&self) -> Option<Duration> {
+        Some(Duration::from_secs(self.rebuild_interval_secs)).filter(|interval| !interval.is_zero())
+    }
+
+    pub fn snapshot_dir(&self) -> PathBuf {
+        self.data_dir.join(SNAPSHOT_DIR)
+    }
+
+    /// Environment of the receipt cache, which snapshots leave out.
+    pub fn receipts_dir(&self) -> PathBuf {
+        self.data_dir.join(RECEIPTS_DIR)
+    }
+
+    /// Number of results for a search, or `None` if `top_k` is out of bounds.
+    pub fn top_k(&self, top_k: Option<usize>) -> Option<usize> {
+        match top_k.unwrap_or(self.default_top_k) {
+            0 => None,
+            top_k if top_k > self.max_top_k => None,
+            top_k => Some(top_k),
+        }
+    }
+
+    /// Threshold for a search, or `None` if it is not a cosine similarity.
+    pub fn threshold(&self, threshold: Option<f32>) -> Option<f32> {
+        Some(threshold.unwrap_or(self.default_threshold)).filter(|threshold| (-1.0..=1.0).contains(threshold))
+    }
+
+    /// Must run before any thread is started, the prover reads `RISC0_DEV_MODE` from the environment.
+    pub fn apply_proving_mode(&self) {
+        // SAFETY: called from `main` before the tokio runtime or any other thread exists.
+        unsafe {
+            match self.proving {
+                ProvingMode::Dev => std::env::set_var("RISC0_DEV_MODE", "1"),
+                ProvingMode::Local => std::env::remove_var("RISC0_DEV_MODE"),
+                ProvingMode::None => {}
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn file_then_flags_test() {
+        let settings: Settings = toml::from_str(
+            r#"
+            listen = "0.0.0.0:4000"
+            data_dir = "/var/lib/docsim"
+            metric = "cosine"
+            max_top_k = 50
+            proving = "dev"
+            similarity_mode = "fixed"
+            "#,
+        )
+        .unwrap();
+        assert_eq!(Metric::Cosine, settings.metric);
+        assert_eq!(ProvingMode::Dev, settings.proving);
+        assert_eq!(SimilarityMode::Fixed, settings.similarity_mode);
+        assert_eq!(DEFAULT_TOP_K, settings.default_top_k);
+
+        let cli = Cli::parse_from(["backend", "--listen", "127.0.0.1:4001", "--proving", "none"]);
+        let mut merged = settings.clone();
+        merged.merge(&cli);
+        assert_eq!("127.0.0.1:4001".parse::<SocketAddr>().unwrap(), merged.listen);
+        assert_eq!(ProvingMode::None, merged.proving);
+        assert_eq!(PathBuf::from("/var/lib/docsim"), merged.data_dir);
+        assert_eq!(Metric::Cosine, merged.metric);
+
+        let cli = Cli::parse_from(["backend", "--rebuild-threshold", "10", "--rebuild-interval-secs", "0"]);
+        merged.merge(&cli);
+        assert_eq!(10, merged.rebuild_threshold);
+        assert_eq!(None, merged.rebuild_interval());
+        assert_eq!(Some(Duration::from_secs(30)), settings.rebuild_interval());
+    }
+
+    #[test]
+    fn validate_test() {
+        let mut settings = Settings::default();
+        settings.validate().unwrap();
+        assert_eq!(EmbeddingModel::AllMiniLML6V2, settings.embedding_model().unwrap().0);
+        assert_eq!(384, settings.dimensions());
+        assert_eq!(Some(DEFAULT_TOP_K), settings.top_k(None));
+        assert_eq!(None, settings.top_k(Some(DEFAULT_MAX_TOP_K + 1)));
+        assert_eq!(None, settings.top_k(Some(0)));
+
+        let mut wrong_dims = Settings { dimensions: Some(768), ..Settings::default() };
+        assert!(wrong_dims.validate().is_err());
+        let mut unknown_model = Settings { model: "not-a-model".to_string(), ..Settings::default() };
+        assert!(unknown_model.validate().is_err());
+        let mut bad_top_k = Settings { default_top_k: 30, ..Settings::default() };
+        assert!(bad_top_k.validate().is_err());
+        assert!(toml::from_str::<Settings>("lisen = \"0.0.0.0:1\"").is_err());
+    }
+
+    #[test]
+    fn threshold_test() {
+        let settings = Settings::default();
+        assert_eq!(Some(DEFAULT_THRESHOLD), settings.threshold(None));
+        assert_eq!(Some(0.95), settings.threshold(Some(0.95)));
+        assert_eq!(None, settings.threshold(Some(1.5)));
+        assert_eq!(None, settings.threshold(Some(f32::NAN)));
+        let mut bad_threshold = Settings { default_threshold: -2.0, ..Settings::default() };
+        assert!(bad_threshold.validate().is_err());
+    }
+
+    #[test]
+    fn local_model_test() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut local = Settings {
+            model: "my-model".to_string(),
+            model_dir: Some(dir.path().to_path_buf()),
+            ..Settings::default()
+        };
+        // the dimensions of a local model are not known before loading it
+        assert!(local.validate().is_err());
+        local.dimensions = Some(768);
+        local.validate().unwrap();
+        assert_eq!(768, local.dimensions());
+        let mut unnamed = Settings { model: DEFAULT_MODEL.to_string(), ..local.clone() };
+        assert!(unnamed.validate().is_err());
+        let mut missing = Settings { model_dir: Some(dir.path().join("missing")), ..local };
+        assert!(missing.validate().is_err());
+    }
+
+    #[test]
+    fn embed_batch_size_test() {
+        let mut bad_batch = Settings { embed_batch_size: 0, ..Settings::default() };
+        assert!(bad_batch.validate().is_err());
+        let cli = Cli::parse_from(["backend", "--embed-batch-size", "8"]);
+        let mut merged = Settings::default();
+        merged.merge(&cli);
+        assert_eq!(8, merged.embed_batch_size);
+    }
+
+    #[test]
+    fn export_import_collection_test() {
+        let cli = Cli::parse_from(["backend", "export", "out.jsonl", "--collection", "team-a"]);
+        assert!(matches!(cli.command, Some(Command::Export { collection, .. }) if collection == "team-a"));
+        let cli = Cli::parse_from(["backend", "import", "in.jsonl"]);
+        assert!(matches!(cli.command, Some(Command::Import { collection, .. }) if collection == DEFAULT_COLLECTION));
+    }
+
+    #[test]
+    fn prefixes_test() {
+        assert_eq!(("", ""), Settings::default().prefixes());
+        let e5 = Settings { model: "intfloat/multilingual-e5-small".to_string(), ..Settings::default() };
+        assert_eq!(("query: ", "passage: "), e5.prefixes());
+        let custom = Settings { query_prefix: Some("q: ".to_string()), ..e5 };
+        assert_eq!(("q: ", "passage: "), custom.prefixes());
+        let none = Settings { passage_prefix: Some(String::new()), ..custom };
+        assert_eq!(("q: ", ""), none.prefixes());
+    }
+}
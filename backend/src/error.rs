@@ -0,0 +1,137 @@
+use std::any::Any;
+
+use axum::body::Body;
+use axum::http::{header, Response, StatusCode};
+use axum::response::IntoResponse;
+use axum::Json;
+use serde::Serialize;
+use tracing::log::error;
+
+use crate::services::simple_db_nn::EmbeddingError;
+
+/// Errors returned by the HTTP API, serialized as `{"code": ..., "message": ...}`.
+#[derive(Debug)]
+pub enum ApiError {
+    /// The request is malformed or out of the configured limits.
+    BadRequest(String),
+    InvalidMetadata(String),
+    InvalidCollectionName(String),
+    DocumentNotFound(u32),
+    CollectionNotFound(String),
+    CollectionExists(String),
+    /// Every database slot of the environment is taken.
+    TooManyCollections(usize),
+    ProofJobNotFound(u64),
+    /// The proof job has not finished yet.
+    ProofNotReady(u64),
+    ProofQueueFull,
+    /// The embedding model failed on the input.
+    Embedding(anyhow::Error),
+    /// The zkVM could not produce a receipt.
+    Prover(anyhow::Error),
+    /// heed or arroy failed.
+    Storage(anyhow::Error),
+    /// A previous handler panicked while holding the database lock.
+    Poisoned,
+}
+
+#[derive(Serialize)]
+pub struct ErrorBody {
+    pub code: &'static str,
+    pub message: String,
+}
+
+impl ApiError {
+    pub fn code(&self) -> &'static str {
+        match self {
+            ApiError::BadRequest(_) => "bad_request",
+            ApiError::InvalidMetadata(_) => "invalid_metadata",
+            ApiError::InvalidCollectionName(_) => "invalid_collection_name",
+            ApiError::DocumentNotFound(_) => "document_not_found",
+            ApiError::CollectionNotFound(_) => "collection_not_found",
+            ApiError::CollectionExists(_) => "collection_exists",
+            ApiError::TooManyCollections(_) => "too_many_collections",
+            ApiError::ProofJobNotFound(_) => "proof_job_not_found",
+            ApiError::ProofNotReady(_) => "proof_not_ready",
+            ApiError::ProofQueueFull => "proof_queue_full",
+            ApiError::Embedding(_) => "embedding_failed",
+            ApiError::Prover(_) => "prover_failed",
+            ApiError::Storage(_) => "storage_error",
+            ApiError::Poisoned => "database_unavailable",
+        }
+    }
+
+    pub fn status(&self) -> StatusCode {
+        match self {
+            ApiError::BadRequest(_) | ApiError::InvalidMetadata(_) | ApiError::InvalidCollectionName(_) => {
+                StatusCode::BAD_REQUEST
+            }
+            ApiError::DocumentNotFound(_) | ApiError::CollectionNotFound(_) | ApiError::ProofJobNotFound(_) => {
+                StatusCode::NOT_FOUND
+            }
+            ApiError::CollectionExists(_) | ApiError::TooManyCollections(_) | ApiError::ProofNotReady(_) => {
+                StatusCode::CONFLICT
+            }
+            ApiError::ProofQueueFull => StatusCode::SERVICE_UNAVAILABLE,
+            ApiError::Embedding(_) | ApiError::Prover(_) | ApiError::Storage(_) | ApiError::Poisoned => {
+                StatusCode::INTERNAL_SERVER_ERROR
+            }
+        }
+    }
+
+    pub fn message(&self) -> String {
+        match self {
+            ApiError::BadRequest(message) => message.clone(),
+            ApiError::InvalidMetadata(err) => format!("Metadata is not valid JSON: {}", err),
+            ApiError::InvalidCollectionName(name) => format!("Invalid collection name: {}", name),
+            ApiError::DocumentNotFound(id) => format!("Document {} not found", id),
+            ApiError::CollectionNotFound(name) => format!("Collection {} not found", name),
+            ApiError::CollectionExists(name) => format!("Collection {} already exists", name),
+            ApiError::TooManyCollections(max) => format!("At most {} collections can exist, drop one first", max),
+            ApiError::ProofJobNotFound(id) => format!("Proof job {} not found", id),
+            ApiError::ProofNotReady(id) => format!("Proof job {} has no receipt", id),
+            ApiError::ProofQueueFull => "Too many pending proofs, retry later".to_string(),
+            ApiError::Embedding(err) => format!("Embedding failed: {}", err),
+            ApiError::Prover(err) => format!("Proof generation failed: {}", err),
+            ApiError::Storage(err) => format!("Storage error: {}", err),
+            ApiError::Poisoned => "The database is unavailable after an internal error".to_string(),
+        }
+    }
+}
+
+impl From<anyhow::Error> for ApiError {
+    fn from(err: anyhow::Error) -> Self {
+        match err.downcast::<EmbeddingError>() {
+            Ok(EmbeddingError(err)) => ApiError::Embedding(err),
+            Err(err) => ApiError::Storage(err),
+        }
+    }
+}
+
+impl IntoResponse for ApiError {
+    fn into_response(self) -> axum::response::Response {
+        let status = self.status();
+        if status.is_server_error() {
+            error!("Err={:?}", self.message());
+        }
+        (status, Json(ErrorBody { code: self.code(), message: self.message() })).into_response()
+    }
+}
+
+/// Used by `CatchPanicLayer`, so a panicking handler answers with a 500 instead of dropping the connection.
+pub fn panic_response(err: Box<dyn Any + Send + 'static>) -> Response<Body> {
+    let details = if let Some(message) = err.downcast_ref::<String>() {
+        message.clone()
+    } else if let Some(message) = err.downcast_ref::<&str>() {
+        message.to_string()
+    } else {
+        "unknown panic".to_string()
+    };
+    error!("Handler panicked: {}", details);
+    let body = ErrorBody { code: "internal_error", message: "Internal server error".to_string() };
+    Response::builder()
+        .status(StatusCode::INTERNAL_SERVER_ERROR)
+        .header(header::CONTENT_TYPE, "application/json")
+        .body(Body::from(serde_json::to_vec(&body).unwrap_or_default()))
+        .expect("valid panic response")
+}
@@ -8,6 +8,8 @@
 
 extern crate core;
 
+mod config;
+mod error;
 mod services;
 
 use axum::{extract::{
@@ -17,27 +19,35 @@ use axum::{extract::{
 use tower_http::services::{ServeDir, ServeFile};
 
 use futures_util::stream::StreamExt;
-use std::{
-    collections::HashSet,
-    sync::{Arc, Mutex},
-};
-use arroy::distances::Euclidean;
+use std::sync::{Arc, Mutex, MutexGuard};
 use tower_http::cors::CorsLayer;
 use tower_http::cors::Any;
+use tower_http::catch_panic::CatchPanicLayer;
 use tracing_subscriber::{layer::SubscriberExt, util::SubscriberInitExt};
 
 use axum::{
-    extract::Multipart,
-    routing::post
+    extract::{Path, Query},
+    routing::{delete, post}
 };
 
-use axum::debug_handler;
 use axum::http::StatusCode;
-use fastembed::EmbeddingModel::ModernBertEmbedLarge;
 use serde::{Deserialize, Serialize};
 use tracing::log::error;
-use services::embed::{DocumentEntry, ModelEmbed};
-use crate::services::simple_db_nn::{DBConfig, SimpleDBNN};
+use services::embed::ModelEmbed;
+use clap::Parser;
+use crate::config::{Cli, Command, ProvingMode, Settings, SimilarityMode};
+use crate::error::{panic_response, ApiError};
+use crate::services::corpus_tree::{CorpusRoot, InclusionProof};
+use crate::services::metric::Metric;
+use crate::services::proofs::{Guest, JobId, JobState, Journal, ProofInput, ProofQueue, Prover};
+use crate::services::receipts::ReceiptStore;
+use docsim_common::fixed::THRESHOLD_SCALE;
+use host::{BatchCandidate, BatchSimilarityInput, FixedSimilarityInput, RankingInput};
+use crate::services::simple_db_nn::{
+    is_valid_collection_name, Collection, DBConfig, Embeddable, SimpleDBNN, DEFAULT_COLLECTION, MAX_COLLECTIONS,
+};
+use crate::services::snapshot::SnapshotManifest;
+
 
 #[derive(Serialize)]
 struct EmbeddingResponse {
@@ -49,19 +59,52 @@ struct EmbeddingResponse {
 pub struct UploadFileForm {
     name: Option<String>,
     content: Option<String>,
+    /// JSON object stored alongside the document, e.g. `{"tenant": "a", "tags": ["x"]}`
+    metadata: Option<String>,
+    collection: Option<String>,
+}
+
+#[derive(Deserialize)]
+pub struct CollectionQuery {
+    collection: Option<String>,
+}
+
+fn collection_or_default(collection: Option<String>) -> String {
+    collection.unwrap_or_else(|| DEFAULT_COLLECTION.to_string())
+}
+
+/// Selects the collection named in the request, the default one if none is given.
+fn use_collection(memory_db: &mut SimpleDBNN<Arc<ModelEmbed>>, collection: Option<String>) -> Result<(), ApiError> {
+    let collection = collection_or_default(collection);
+    if memory_db.use_collection(&collection)? {
+        Ok(())
+    } else {
+        Err(ApiError::CollectionNotFound(collection))
+    }
 }
 
 
 
 // Our shared state
 struct AppState {
-    memory_db: Mutex<SimpleDBNN<ModelEmbed, Euclidean>>
+    memory_db: Mutex<SimpleDBNN<Arc<ModelEmbed>>>,
+    /// The embedding engine of `memory_db`. Queries are embedded with it before taking
+    /// the lock, and the same vector is used for the lookup and the proof.
+    embedder: Arc<ModelEmbed>,
+    settings: Settings,
+    /// `None` when proving is disabled.
+    proofs: Option<Arc<ProofQueue>>,
     // Channel used to send messages to all connected clients.
    // tx: broadcast::Sender<String>,
 }
 
-#[tokio::main]
-async fn main() {
+impl AppState {
+    fn db(&self) -> Result<MutexGuard<'_, SimpleDBNN<Arc<ModelEmbed>>>, ApiError> {
+        self.memory_db.lock().map_err(|_| ApiError::Poisoned)
+    }
+}
+
+fn main() {
     tracing_subscriber::registry()
         .with(
             tracing_subscriber::EnvFilter::try_from_default_env()
@@ -70,11 +113,103 @@ async fn main() {
         .with(tracing_subscriber::fmt::layer())
         .init();
 
+    let cli = Cli::parse();
+    let settings = match Settings::load(&cli) {
+        Ok(settings) => settings,
+        Err(err) => {
+            error!("Err={:?}", err.to_string());
+            std::process::exit(1);
+        }
+    };
+    settings.apply_proving_mode();
+
+    if let Some(command) = &cli.command {
+        if let Err(err) = run_command(command, &settings) {
+            error!("Err={:?}", err.to_string());
+            std::process::exit(1);
+        }
+        return;
+    }
+
+    let served = tokio::runtime::Runtime::new()
+        .expect("Failed to start the tokio runtime")
+        .block_on(serve(settings));
+    if let Err(err) = served {
+        error!("Err={:?}", err.to_string());
+        std::process::exit(1);
+    }
+}
+
+fn db_config(settings: &Settings) -> anyhow::Result<DBConfig<Arc<ModelEmbed>>> {
+    let embed_engine = match &settings.model_dir {
+        Some(model_dir) => ModelEmbed::from_dir(model_dir, &settings.model, settings.pooling.into())?,
+        None => ModelEmbed::with_model(settings.embedding_model()?.0)?,
+    };
+    let (query_prefix, passage_prefix) = settings.prefixes();
+    let embed_engine = embed_engine
+        .with_batch_size(settings.embed_batch_size)
+        .with_prefixes(query_prefix, passage_prefix);
+    Ok(DBConfig {
+        dimensions: settings.dimensions(),
+        metric: settings.metric,
+        rebuild_threshold: settings.rebuild_threshold,
+        rebuild_interval: settings.rebuild_interval(),
+        ..DBConfig::with_engine(&settings.data_dir, Arc::new(embed_engine))
+    })
+}
+
+async fn serve(settings: Settings) -> anyhow::Result<()> {
     // Set up application state for use with with_state().
-    let memory_db = Mutex::new(SimpleDBNN::from_config(DBConfig::default()).unwrap());
-    let app_state = Arc::new(AppState {memory_db});
+    let db_config = db_config(&settings)?;
+    let rebuild_interval = db_config.rebuild_interval;
+    let embedder = db_config.embed_engine.clone();
+    let mut simple_db: SimpleDBNN<Arc<ModelEmbed>> = SimpleDBNN::from_config(db_config)?;
+    for report in simple_db.check_consistency(true)? {
+        if !report.is_consistent() {
+            tracing::warn!("Repaired drift between heed and arroy: {:?}", report);
+        }
+    }
+    let receipts = ReceiptStore::open(
+        &settings.receipts_dir(),
+        settings.receipt_cache_entries,
+        settings.receipt_cache_bytes,
+    )?;
+    let (cached, cached_bytes) = receipts.usage()?;
+    tracing::info!("Receipt cache holds {} receipts, {} bytes", cached, cached_bytes);
+    let memory_db = Mutex::new(simple_db);
+    let listen = settings.listen;
+    let static_dir = settings.static_dir.clone();
+    let proofs = match settings.proving {
+        ProvingMode::None => None,
+        _ => {
+            let prover: Prover = Arc::new(ProofInput::prove);
+            Some(ProofQueue::start(
+                prover,
+                Some(Arc::new(receipts)),
+                settings.proof_workers,
+                settings.proof_queue_size,
+                settings.max_finished_proofs,
+            ))
+        }
+    };
+    let app_state = Arc::new(AppState {memory_db, embedder, settings, proofs});
+
+    // Build buffered inserts even when no further writes or reads arrive.
+    if let Some(rebuild_interval) = rebuild_interval {
+        let flush_state = app_state.clone();
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(rebuild_interval);
+            loop {
+                interval.tick().await;
+                let flushed = flush_state.db().and_then(|mut memory_db| memory_db.flush().map_err(ApiError::from));
+                if let Err(err) = flushed {
+                    error!("Err={:?}", err.message());
+                }
+            }
+        });
+    }
 
-    let serve_dir = ServeDir::new("web/verifier").not_found_service(ServeFile::new("web/verifier/index.html"));
+    let serve_dir = ServeDir::new(&static_dir).not_found_service(ServeFile::new(static_dir.join("index.html")));
     //let yew_serve_dir = ServeDir::new("web/yew").not_found_service(ServeFile::new("web/yew/index.html"));
 
     let cors = CorsLayer::new()
@@ -90,14 +225,59 @@ async fn main() {
         .route("/ws", get(websocket_handler))
         .route("/upload", post(upload_file))
         .route("/search", post(search))
+        .route("/search/ranked", post(search_ranked))
+        .route("/corpus/root", get(corpus_root))
+        .route("/guest/image-id", get(guest_image_ids))
+        .route("/documents/{id}", delete(delete_document).put(update_document))
+        .route("/collections", get(list_collections).post(create_collection))
+        .route("/collections/{name}", delete(drop_collection))
+        .route("/admin/flush", post(flush))
+        .route("/admin/rebuild", post(rebuild))
+        .route("/admin/snapshot", post(snapshot))
+        .route("/proofs/{job_id}", get(proof_status))
+        .route("/proofs/{job_id}/receipt", get(proof_receipt))
+        .layer(CatchPanicLayer::custom(panic_response))
         .with_state(app_state);
     
 
-    let listener = tokio::net::TcpListener::bind("127.0.0.1:3000")
-        .await
-        .unwrap();
-    tracing::debug!("listening on {}", listener.local_addr().unwrap());
-    axum::serve(listener, app).await.unwrap();
+    let listener = tokio::net::TcpListener::bind(listen).await?;
+    tracing::debug!("listening on {}", listener.local_addr()?);
+    axum::serve(listener, app).await?;
+    Ok(())
+}
+
+fn run_command(command: &Command, settings: &Settings) -> anyhow::Result<()> {
+    match command {
+        Command::Snapshot { archive } => {
+            let mut simple_db: SimpleDBNN<Arc<ModelEmbed>> = SimpleDBNN::from_config(db_config(settings)?)?;
+            let manifest = simple_db.snapshot(archive)?;
+            println!("Snapshot written to {:?}: {}", archive, serde_json::to_string_pretty(&manifest)?);
+        }
+        Command::Restore { archive } => {
+            let _: SimpleDBNN<Arc<ModelEmbed>> = SimpleDBNN::restore(archive, db_config(settings)?)?;
+            println!("Snapshot {:?} restored", archive);
+        }
+        Command::Export { file, with_embeddings, collection } => {
+            let mut simple_db: SimpleDBNN<Arc<ModelEmbed>> = SimpleDBNN::from_config(db_config(settings)?)?;
+            if !simple_db.use_collection(collection)? {
+                anyhow::bail!("Collection {:?} not found", collection);
+            }
+            let out = std::io::BufWriter::new(std::fs::File::create(file)?);
+            let count = simple_db.export_jsonl(out, *with_embeddings)?;
+            println!("Exported {} documents to {:?}", count, file);
+        }
+        Command::Import { file, collection } => {
+            let mut simple_db: SimpleDBNN<Arc<ModelEmbed>> = SimpleDBNN::from_config(db_config(settings)?)?;
+            if !simple_db.use_collection(collection)? {
+                anyhow::bail!("Collection {:?} not found", collection);
+            }
+            let input = std::io::BufReader::new(std::fs::File::open(file)?);
+            let count = simple_db.import_jsonl(input)?;
+            simple_db.flush()?;
+            println!("Imported {} documents from {:?}", count, file);
+        }
+    }
+    Ok(())
 }
 
 async fn websocket_handler(
@@ -110,7 +290,7 @@ async fn websocket_handler(
 // This function deals with a single websocket connection, i.e., a single
 // connected client / user, for which we will spawn two independent tasks (for
 // receiving / sending chat messages).
-async fn websocket(mut stream: WebSocket, state: Arc<AppState>) {
+async fn websocket(mut stream: WebSocket, _state: Arc<AppState>) {
     println!("WebSocket connection established");
 
     while let Some(Ok(msg)) = stream.next().await {
@@ -127,85 +307,420 @@ async fn websocket(mut stream: WebSocket, state: Arc<AppState>) {
 
 async fn upload_file(
     State(state): State<Arc<AppState>>,
-    Form(form): Form<UploadFileForm>) -> Result<Json<EmbeddingResponse>, (StatusCode, Json<EmbeddingResponse>)>   {
+    Form(form): Form<UploadFileForm>) -> Result<Json<EmbeddingResponse>, ApiError> {
     let name = form.name.unwrap_or(String::new());
     let content = form.content.unwrap_or(String::new());
 
     if content.is_empty() {
-        return Err((
-            StatusCode::BAD_REQUEST,
-            Json(EmbeddingResponse {
-                embedding: vec![],
-            }),
-        ));
+        return Err(ApiError::BadRequest("content must not be empty".to_string()));
     }
+    let metadata = match form.metadata.filter(|metadata| !metadata.is_empty()) {
+        Some(metadata) => serde_json::from_str(&metadata).map_err(|err| ApiError::InvalidMetadata(err.to_string()))?,
+        None => serde_json::Value::Null,
+    };
     let query = format!("{:}\n{:}", name, content);
-    let embedding = state.memory_db.lock().unwrap().put(query.as_str()).map_err(|err| {
-        (
-            StatusCode::INTERNAL_SERVER_ERROR,
-            Json(EmbeddingResponse {
-                embedding: vec![], // or include error message in another field
-            }),
-        )
-    })?;
+    let mut memory_db = state.db()?;
+    use_collection(&mut memory_db, form.collection)?;
+    let embedding = memory_db.put_with_metadata(query.as_str(), metadata)?;
     Ok(Json(EmbeddingResponse { embedding }))
 }
 
+async fn update_document(
+    State(state): State<Arc<AppState>>,
+    Path(id): Path<u32>,
+    Query(query): Query<CollectionQuery>,
+    Form(form): Form<UploadFileForm>) -> Result<Json<EmbeddingResponse>, ApiError> {
+    let name = form.name.unwrap_or(String::new());
+    let content = form.content.unwrap_or(String::new());
+
+    if content.is_empty() {
+        return Err(ApiError::BadRequest("content must not be empty".to_string()));
+    }
+    let content = format!("{:}\n{:}", name, content);
+    let mut memory_db = state.db()?;
+    use_collection(&mut memory_db, query.collection.or(form.collection))?;
+    match memory_db.update(id, content.as_str())? {
+        Some(embedding) => Ok(Json(EmbeddingResponse { embedding })),
+        None => Err(ApiError::DocumentNotFound(id)),
+    }
+}
+
+async fn delete_document(
+    State(state): State<Arc<AppState>>,
+    Path(id): Path<u32>,
+    Query(query): Query<CollectionQuery>) -> Result<StatusCode, ApiError> {
+    let mut memory_db = state.db()?;
+    use_collection(&mut memory_db, query.collection)?;
+    if memory_db.delete(id)? {
+        Ok(StatusCode::NO_CONTENT)
+    } else {
+        Err(ApiError::DocumentNotFound(id))
+    }
+}
+
+async fn flush(State(state): State<Arc<AppState>>) -> Result<StatusCode, ApiError> {
+    state.db()?.flush()?;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+async fn rebuild(State(state): State<Arc<AppState>>, Query(query): Query<CollectionQuery>) -> Result<StatusCode, ApiError> {
+    let mut memory_db = state.db()?;
+    use_collection(&mut memory_db, query.collection)?;
+    memory_db.rebuild()?;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+#[derive(Serialize)]
+struct SnapshotResponse {
+    path: std::path::PathBuf,
+    manifest: SnapshotManifest,
+}
+
+async fn snapshot(State(state): State<Arc<AppState>>) -> Result<Json<SnapshotResponse>, ApiError> {
+    let created_at = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map_err(|err| ApiError::Storage(err.into()))?
+        .as_secs();
+    let path = state.settings.snapshot_dir().join(format!("snapshot-{}.zkds", created_at));
+    // the lock keeps heed and arroy copied at the same point, requests wait for the copy
+    let manifest = state.db()?.snapshot(&path)?;
+    Ok(Json(SnapshotResponse { path, manifest }))
+}
+
+#[derive(Deserialize)]
+struct CreateCollectionRequest {
+    name: String,
+    dimensions: Option<usize>,
+    metric: Option<Metric>,
+}
+
+async fn list_collections(State(state): State<Arc<AppState>>) -> Result<Json<Vec<Collection>>, ApiError> {
+    let collections = state.db()?.list_collections()?;
+    Ok(Json(collections))
+}
+
+async fn create_collection(
+    State(state): State<Arc<AppState>>,
+    Json(req): Json<CreateCollectionRequest>) -> Result<(StatusCode, Json<Collection>), ApiError> {
+    if !is_valid_collection_name(&req.name) {
+        return Err(ApiError::InvalidCollectionName(req.name));
+    }
+    let dimensions = req.dimensions.unwrap_or(state.settings.dimensions());
+    if dimensions != state.settings.dimensions() {
+        return Err(ApiError::BadRequest(format!(
+            "The model {} produces {} dimensions, got {}",
+            state.settings.model, state.settings.dimensions(), dimensions
+        )));
+    }
+    let mut memory_db = state.db()?;
+    if memory_db.list_collections()?.len() >= MAX_COLLECTIONS {
+        return Err(ApiError::TooManyCollections(MAX_COLLECTIONS));
+    }
+    let metric = req.metric.unwrap_or(state.settings.metric);
+    match memory_db.create_collection(&req.name, dimensions, metric)? {
+        Some(collection) => Ok((StatusCode::CREATED, Json(collection))),
+        None => Err(ApiError::CollectionExists(req.name)),
+    }
+}
+
+async fn drop_collection(
+    State(state): State<Arc<AppState>>,
+    Path(name): Path<String>) -> Result<StatusCode, ApiError> {
+    if name == DEFAULT_COLLECTION {
+        return Err(ApiError::BadRequest("The default collection can not be dropped".to_string()));
+    }
+    if state.db()?.drop_collection(&name)? {
+        Ok(StatusCode::NO_CONTENT)
+    } else {
+        Err(ApiError::CollectionNotFound(name))
+    }
+}
+
 #[derive(Deserialize)]
 struct SearchRequest {
     content: String,
-    top_k: usize,
+    /// Defaults to `default_top_k`, at most `max_top_k`.
+    #[serde(default)]
+    top_k: Option<usize>,
+    /// Only documents whose metadata matches every key are considered.
+    #[serde(default)]
+    filter: Option<serde_json::Map<String, serde_json::Value>>,
+    #[serde(default)]
+    collection: Option<String>,
+    /// Cosine similarity the receipts attest against, defaults to `default_threshold`.
+    #[serde(default)]
+    threshold: Option<f32>,
+}
+
+#[derive(Serialize)]
+struct SearchResponse {
+    /// Embedding of the searched content, every receipt commits its digest.
+    query_embedding: Vec<f32>,
+    results: Vec<SearchResult>,
+    /// With `float`, the batch receipt attesting every result in order, poll `/proofs/{proof_job}`.
+    /// `None` with `fixed`, whose results have a receipt each, and when proving is disabled.
+    proof_job: Option<JobId>,
 }
 
 #[derive(Serialize)]
 struct SearchResult {
     id: u32,
     content: String,
+    /// Cosine similarity as computed by the server, the value the receipt should attest.
     score: f32,
+    /// The receipt also attests whether `score` is above it.
+    threshold: f32,
+    /// With `fixed`, `score` and `threshold` are those of the quantized embeddings.
+    similarity_mode: SimilarityMode,
+    /// Distance reported by the index, in the collection metric.
+    distance: f32,
+    metric: Metric,
     embedding: Vec<f32>,
-    receipt: Vec<u8>,
+    /// With `fixed`, poll `/proofs/{proof_job}` for the receipt of this result, otherwise `None`.
+    /// The journal commits digests of the query embedding, `embedding`, `id` and `content`.
+    proof_job: Option<JobId>,
+    /// Decoded from the receipt when it was already proven, e.g. found in the receipt store.
+    /// Otherwise the `journal` of the proof job holds them once it is done.
+    proven_similarity: Option<f32>,
+    above_threshold: Option<bool>,
+    /// Shows that `content` and `embedding` are stored under `id` in the corpus published at `/corpus/root`.
+    inclusion_proof: InclusionProof,
 }
 
-impl SearchResult {
-    pub fn new(id: u32, score: f32,  content: String, embedding: Vec<f32>, receipt: Vec<u8>) -> Self {
-        Self { id, content, score, embedding, receipt }
+async fn search(State(state): State<Arc<AppState>>, Json(req): Json<SearchRequest>) -> Result<Json<SearchResponse>, ApiError> {
+    let Some(top_k) = state.settings.top_k(req.top_k) else {
+        return Err(ApiError::BadRequest(format!(
+            "top_k must be between 1 and {}",
+            state.settings.max_top_k
+        )));
+    };
+    let Some(threshold) = state.settings.threshold(req.threshold) else {
+        return Err(ApiError::BadRequest("threshold must be between -1 and 1".to_string()));
+    };
+    let original_embed = state.embedder.to_query_embedding(req.content.as_bytes()).map_err(ApiError::Embedding)?;
+    let mut memory_db = state.db()?;
+    use_collection(&mut memory_db, req.collection.clone())?;
+    let results = match &req.filter {
+        Some(filter) => memory_db.get_filtered_by_embedding(&original_embed, top_k, |entry| entry.matches(filter)),
+        None => memory_db.get_by_embedding(&original_embed, top_k),
+    }?;
+    let metric = memory_db.metric;
+    // taken under the same lock, so every proof is against the same root
+    let mut inclusion_proofs = Vec::with_capacity(results.len());
+    for (id, _, _) in &results {
+        inclusion_proofs.push(memory_db.inclusion_proof(*id)?.ok_or(ApiError::DocumentNotFound(*id))?);
+    }
+    drop(memory_db);
+
+    let mut scored = Vec::with_capacity(results.len());
+    let mut fixed_inputs = Vec::new();
+    for (id, _, entry) in &results {
+        match state.settings.similarity_mode {
+            SimilarityMode::Float => scored.push((host::cosine_similarity(&original_embed, &entry.embedding), threshold)),
+            SimilarityMode::Fixed => {
+                let input = FixedSimilarityInput::new(&original_embed, &entry.embedding, *id, entry.content.clone(), threshold);
+                scored.push((input.similarity().cosine() as f32, input.threshold as f32 / THRESHOLD_SCALE as f32));
+                fixed_inputs.push(ProofInput::FixedSimilarity(input));
+            }
+        }
     }
+    // a full queue fails the search without leaving jobs behind that no response names
+    let (proof_job, proof_jobs): (Option<JobId>, Vec<Option<JobId>>) = match (&state.proofs, state.settings.similarity_mode) {
+        (None, _) => (None, vec![None; results.len()]),
+        // one receipt of the batch guest covers every result
+        (Some(proofs), SimilarityMode::Float) => {
+            let input = BatchSimilarityInput {
+                query: original_embed.clone(),
+                candidates: results
+                    .iter()
+                    .map(|(id, _, entry)| BatchCandidate { embedding: entry.embedding.clone(), id: *id, content: entry.content.clone() })
+                    .collect(),
+                threshold,
+            };
+            let job_id = proofs.submit(ProofInput::BatchSimilarity(input)).ok_or(ApiError::ProofQueueFull)?;
+            (Some(job_id), vec![None; results.len()])
+        }
+        // there is no batch guest for quantized embeddings, every result gets its own receipt
+        (Some(proofs), SimilarityMode::Fixed) => {
+            let job_ids = proofs.submit_all(fixed_inputs).ok_or(ApiError::ProofQueueFull)?;
+            (None, job_ids.into_iter().map(Some).collect())
+        }
+    };
+    let batch_journal = match proof_job {
+        Some(job_id) => proven_journal(&state, job_id)?,
+        None => None,
+    };
+
+    let mut search_results = Vec::with_capacity(results.len());
+    for (index, ((((id, distance, entry), inclusion_proof), (score, threshold)), proof_job)) in
+        results.into_iter().zip(inclusion_proofs).zip(scored).zip(proof_jobs).enumerate()
+    {
+        let journal = match proof_job {
+            Some(job_id) => proven_journal(&state, job_id)?,
+            None => None,
+        };
+        let journal = journal.as_ref().or(batch_journal.as_ref());
+        search_results.push(SearchResult {
+            id,
+            content: entry.content,
+            score,
+            threshold,
+            similarity_mode: state.settings.similarity_mode,
+            distance,
+            metric,
+            embedding: entry.embedding,
+            proof_job,
+            proven_similarity: journal.and_then(|journal| journal.proven_similarity(index)),
+            above_threshold: journal.and_then(|journal| journal.above_threshold(index)),
+            inclusion_proof,
+        });
+    }
+    Ok(Json(SearchResponse { query_embedding: original_embed, results: search_results, proof_job }))
 }
 
-async fn search(State(state): State<Arc<AppState>>, Json(req): Json<SearchRequest>)->
-                                                                                   Result<
-                                                                                       Json<Vec<SearchResult>>,
-                                                                                       (StatusCode, Json<Vec<SearchResult>>)
-                                                                                   > {
+#[derive(Serialize)]
+struct RankedResult {
+    id: u32,
+    content: String,
+    /// Distance in the collection metric, as computed by the ranking guest.
+    distance: f32,
+}
 
-    let original_embed =  ModelEmbed::new().calculate_one_embed(DocumentEntry{content: req.content.clone()})
-    .map_err(|err| {
-        error!("Err={:?}", err.to_string());
-        (
-            StatusCode::INTERNAL_SERVER_ERROR,
-            Json(Vec::new()),
-        )
-    })?;
-        ;
-    let results = state.memory_db.lock().unwrap().get(req.content.as_str(), req.top_k).map_err(|err| {
-        error!("Err={:?}", err.to_string());
-        (
+#[derive(Serialize)]
+struct RankedSearchResponse {
+    /// Hex Merkle root over every `(id, embedding)` of the collection, committed by the receipt.
+    /// Equal to the root published at `/corpus/root` when no write happened in between.
+    corpus_root: String,
+    metric: Metric,
+    results: Vec<RankedResult>,
+    /// Proves that `results` are the exact top-k of the committed corpus.
+    proof_job: Option<JobId>,
+}
 
-            StatusCode::INTERNAL_SERVER_ERROR,
-            Json(Vec::new()),
-        )
-    })?;
-    let search_results = results.iter().map(|(id, score,entry )|
-        {
-
-            let cloned = (*entry).clone();
-            let content = cloned.content;
-            let embedding = cloned.embedding;
-            let receipt =  host::execute_and_serialize_receipt(original_embed.clone(), embedding.clone()).unwrap();
-            SearchResult::new(*id, *score, content, embedding,receipt)
+/// Exact brute-force search, proven over the whole collection instead of per result.
+async fn search_ranked(State(state): State<Arc<AppState>>, Json(req): Json<SearchRequest>) -> Result<Json<RankedSearchResponse>, ApiError> {
+    let Some(top_k) = state.settings.top_k(req.top_k) else {
+        return Err(ApiError::BadRequest(format!(
+            "top_k must be between 1 and {}",
+            state.settings.max_top_k
+        )));
+    };
+    if req.filter.is_some() {
+        return Err(ApiError::BadRequest("Ranked search ranks the whole collection and takes no filter".to_string()));
+    }
+    if req.threshold.is_some() {
+        return Err(ApiError::BadRequest("Ranked search proves distances and takes no threshold".to_string()));
+    }
+    let query = state.embedder.to_query_embedding(req.content.as_bytes()).map_err(ApiError::Embedding)?;
+    let mut memory_db = state.db()?;
+    use_collection(&mut memory_db, req.collection.clone())?;
+    let metric = memory_db.metric;
+    let Some(rank_metric) = metric.rank_metric() else {
+        return Err(ApiError::BadRequest(format!("Ranked search does not support the {} metric", metric)));
+    };
+    let corpus = memory_db.corpus()?;
+    if corpus.len() > state.settings.max_ranked_corpus {
+        return Err(ApiError::BadRequest(format!(
+            "Ranked search supports at most {} documents, the collection has {}",
+            state.settings.max_ranked_corpus,
+            corpus.len()
+        )));
+    }
+    let input = RankingInput { query, k: top_k as u32, metric: rank_metric, corpus };
+    let mut results = Vec::with_capacity(top_k);
+    let corpus = input.corpus.iter().map(|(id, _, embedding)| (*id, embedding.as_slice()));
+    for (id, distance) in docsim_common::ranking::top_k(&input.query, corpus, top_k, rank_metric) {
+        let entry = memory_db.get_db(id)?.ok_or(ApiError::DocumentNotFound(id))?;
+        results.push(RankedResult { id, content: entry.content, distance });
+    }
+    drop(memory_db);
+
+    let corpus_root = hex::encode(input.root());
+    let proof_job = match &state.proofs {
+        Some(proofs) => Some(proofs.submit(ProofInput::Ranking(input)).ok_or(ApiError::ProofQueueFull)?),
+        None => None,
+    };
+    Ok(Json(RankedSearchResponse { corpus_root, metric, results, proof_job }))
+}
+
+/// The current commitment to a collection, against which inclusion proofs are checked.
+async fn corpus_root(State(state): State<Arc<AppState>>, Query(query): Query<CollectionQuery>) -> Result<Json<CorpusRoot>, ApiError> {
+    let mut memory_db = state.db()?;
+    use_collection(&mut memory_db, query.collection)?;
+    Ok(Json(memory_db.corpus_root()?))
+}
+
+/// Image ids of every guest program, as eight u32 words each, for `verify_receipt` and
+/// the other wasm verifier functions.
+#[derive(Serialize)]
+struct GuestImageIds {
+    similarity: [u32; 8],
+    batch_similarity: [u32; 8],
+    fixed_similarity: [u32; 8],
+    ranking: [u32; 8],
+}
+
+async fn guest_image_ids() -> Json<GuestImageIds> {
+    Json(GuestImageIds {
+        similarity: Guest::Similarity.image_id(),
+        batch_similarity: host::batch_image_id(),
+        fixed_similarity: Guest::FixedSimilarity.image_id(),
+        ranking: Guest::Ranking.image_id(),
+    })
+}
+
+#[derive(Serialize)]
+struct ProofStatus {
+    id: JobId,
+    guest: Guest,
+    /// Guest image the receipt verifies against.
+    image_id: [u32; 8],
+    /// What the receipt attests, once the job is done.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    journal: Option<Journal>,
+    #[serde(flatten)]
+    state: JobState,
+}
+
+fn proof_job(state: &AppState, job_id: JobId) -> Result<JobState, ApiError> {
+    state
+        .proofs
+        .as_ref()
+        .and_then(|proofs| proofs.get(job_id))
+        .ok_or(ApiError::ProofJobNotFound(job_id))
+}
+
+async fn proof_status(State(state): State<Arc<AppState>>, Path(job_id): Path<JobId>) -> Result<Json<ProofStatus>, ApiError> {
+    let job_state = proof_job(&state, job_id)?;
+    let guest = state
+        .proofs
+        .as_ref()
+        .and_then(|proofs| proofs.guest(job_id))
+        .ok_or(ApiError::ProofJobNotFound(job_id))?;
+    let journal = proven_journal(&state, job_id)?;
+    Ok(Json(ProofStatus { id: job_id, guest, image_id: guest.image_id(), journal, state: job_state }))
+}
+
+/// The decoded journal of a job, `None` until it is done.
+fn proven_journal(state: &AppState, job_id: JobId) -> Result<Option<Journal>, ApiError> {
+    let Some(proofs) = &state.proofs else {
+        return Ok(None);
+    };
+    match (proofs.get(job_id), proofs.guest(job_id)) {
+        (Some(JobState::Done { receipt }), Some(guest)) => {
+            Ok(Some(guest.decode_journal(&receipt).map_err(ApiError::Prover)?))
         }
-    ).collect::<Vec<SearchResult>>();
-    Ok(Json(search_results))
+        _ => Ok(None),
+    }
+}
+
+/// The bincode receipt of a finished job.
+async fn proof_receipt(State(state): State<Arc<AppState>>, Path(job_id): Path<JobId>) -> Result<Vec<u8>, ApiError> {
+    match proof_job(&state, job_id)? {
+        JobState::Done { receipt } => Ok(receipt),
+        JobState::Failed { error } => Err(ApiError::Prover(anyhow::anyhow!(error))),
+        JobState::Queued | JobState::Running => Err(ApiError::ProofNotReady(job_id)),
+    }
 }
 
 
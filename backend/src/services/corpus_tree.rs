@@ -0,0 +1,168 @@
+use docsim_common::merkle::{self, Hash, NodeStore};
+use heed::types::Bytes;
+use heed::{Database as HeedDatabase, Env, RoTxn, RwTxn};
+use serde::Serialize;
+
+const VERSION_KEY: &[u8] = b"version";
+
+/// Nodes are keyed by `level | index`, big endian, which never collides with `VERSION_KEY`.
+fn node_key(level: u8, index: u32) -> [u8; 5] {
+    let mut key = [level; 5];
+    key[1..].copy_from_slice(&index.to_be_bytes());
+    key
+}
+
+pub fn corpus_tree_db_name(collection: &str) -> String {
+    format!("merkle-{}", collection)
+}
+
+/// Merkle tree over the `(id, embedding)` leaves of one collection, stored in the heed
+/// environment of its documents and updated in the transaction of every write.
+///
+/// The version counts the writes applied to the tree, so a root can be published along
+/// with the version it was taken at.
+#[derive(Clone, Copy)]
+pub struct CorpusTree {
+    db: HeedDatabase<Bytes, Bytes>,
+}
+
+/// Published commitment to the content of a collection.
+#[derive(Serialize, Clone, Debug, PartialEq)]
+pub struct CorpusRoot {
+    pub collection: String,
+    /// Hex Merkle root over the `(id, content, embedding)` of every document.
+    pub root: String,
+    pub version: u64,
+    pub documents: u64,
+}
+
+/// Shows that the document with `id` and the returned content and embedding is a leaf of `root`.
+/// Checked with `merkle::verify_inclusion(root, id, leaf_hash(id, document_digest(id, content), embedding), path)`.
+#[derive(Serialize, Clone, Debug, PartialEq)]
+pub struct InclusionProof {
+    pub root: String,
+    pub version: u64,
+    /// Hex siblings from the leaf up to the root.
+    pub path: Vec<String>,
+}
+
+struct Nodes<'a, 'p> {
+    db: HeedDatabase<Bytes, Bytes>,
+    txn: &'a mut RwTxn<'p>,
+}
+
+impl NodeStore for Nodes<'_, '_> {
+    type Error = heed::Error;
+
+    fn node(&self, level: u8, index: u32) -> Result<Option<Hash>, heed::Error> {
+        Ok(self.db.get(&*self.txn, &node_key(level, index))?.and_then(|node| node.try_into().ok()))
+    }
+
+    fn set_node(&mut self, level: u8, index: u32, node: Option<Hash>) -> Result<(), heed::Error> {
+        match node {
+            Some(node) => self.db.put(self.txn, &node_key(level, index), &node),
+            None => self.db.delete(self.txn, &node_key(level, index)).map(|_| ()),
+        }
+    }
+}
+
+struct ReadNodes<'a, 'e> {
+    db: HeedDatabase<Bytes, Bytes>,
+    txn: &'a RoTxn<'e>,
+}
+
+impl NodeStore for ReadNodes<'_, '_> {
+    type Error = heed::Error;
+
+    fn node(&self, level: u8, index: u32) -> Result<Option<Hash>, heed::Error> {
+        Ok(self.db.get(self.txn, &node_key(level, index))?.and_then(|node| node.try_into().ok()))
+    }
+
+    fn set_node(&mut self, _: u8, _: u32, _: Option<Hash>) -> Result<(), heed::Error> {
+        unreachable!("the tree is only read in a read transaction")
+    }
+}
+
+impl CorpusTree {
+    /// Opens the tree of `collection`, creating it if the collection predates corpus roots.
+    pub fn open(env: &Env, txn: &mut RwTxn, collection: &str) -> anyhow::Result<Self> {
+        let db = env.create_database(txn, Some(&corpus_tree_db_name(collection)))?;
+        Ok(CorpusTree { db })
+    }
+
+    /// Sets the leaf of every id, `None` for removed documents, as one new version.
+    pub fn set_leaves(&self, txn: &mut RwTxn, leaves: &[(u32, Option<Hash>)]) -> anyhow::Result<u64> {
+        let mut nodes = Nodes { db: self.db, txn: &mut *txn };
+        for &(id, leaf) in leaves {
+            merkle::set_leaf(&mut nodes, id, leaf)?;
+        }
+        let version = self.version(txn)? + 1;
+        self.db.put(txn, VERSION_KEY, &version.to_be_bytes())?;
+        Ok(version)
+    }
+
+    /// Replaces the whole tree with `leaves`, given in increasing id order.
+    pub fn rebuild(&self, txn: &mut RwTxn, leaves: &[(u32, Hash)]) -> anyhow::Result<u64> {
+        let version = self.version(txn)?;
+        self.db.clear(txn)?;
+        self.db.put(txn, VERSION_KEY, &version.to_be_bytes())?;
+        let leaves = leaves.iter().map(|&(id, leaf)| (id, Some(leaf))).collect::<Vec<_>>();
+        self.set_leaves(txn, &leaves)
+    }
+
+    /// Deletes the database of the tree, the handle must not be used afterwards.
+    pub fn remove(self, txn: &mut RwTxn) -> anyhow::Result<()> {
+        // SAFETY: callers drop every other copy of the handle before removing the tree
+        unsafe { self.db.remove(txn)? };
+        Ok(())
+    }
+
+    pub fn root(&self, txn: &RoTxn) -> anyhow::Result<Hash> {
+        Ok(merkle::root(&ReadNodes { db: self.db, txn })?)
+    }
+
+    pub fn version(&self, txn: &RoTxn) -> anyhow::Result<u64> {
+        Ok(self
+            .db
+            .get(txn, VERSION_KEY)?
+            .and_then(|bytes| bytes.try_into().ok())
+            .map_or(0, u64::from_be_bytes))
+    }
+
+    /// Siblings from the leaf of `id` up to the root, see [`merkle::verify_inclusion`].
+    pub fn inclusion_path(&self, txn: &RoTxn, id: u32) -> anyhow::Result<Vec<Hash>> {
+        Ok(merkle::inclusion_path(&ReadNodes { db: self.db, txn }, id)?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use docsim_common::merkle::{leaf_hash, merkle_root, verify_inclusion};
+    use heed::EnvOpenOptions;
+
+    #[test]
+    fn corpus_tree_test() {
+        let dir = tempfile::tempdir().unwrap();
+        let env = unsafe { EnvOpenOptions::new().map_size(10 * 1024 * 1024).max_dbs(10).open(dir.path()) }.unwrap();
+        let mut wtxn = env.write_txn().unwrap();
+        let tree = CorpusTree::open(&env, &mut wtxn, "test").unwrap();
+        let leaves = (0..3).map(|id| (id, leaf_hash(id, &[1; 32], &[id as f32]))).collect::<Vec<(u32, Hash)>>();
+        tree.set_leaves(&mut wtxn, &[(0, Some(leaves[0].1)), (1, Some(leaves[1].1))]).unwrap();
+        tree.set_leaves(&mut wtxn, &[(2, Some(leaves[2].1)), (1, None)]).unwrap();
+        wtxn.commit().unwrap();
+
+        let rtxn = env.read_txn().unwrap();
+        let root = tree.root(&rtxn).unwrap();
+        assert_eq!(merkle_root(&[leaves[0], leaves[2]]), root);
+        assert_eq!(2, tree.version(&rtxn).unwrap());
+        assert!(verify_inclusion(&root, 2, &leaves[2].1, &tree.inclusion_path(&rtxn, 2).unwrap()));
+        assert!(!verify_inclusion(&root, 1, &leaves[1].1, &tree.inclusion_path(&rtxn, 1).unwrap()));
+        drop(rtxn);
+
+        let mut wtxn = env.write_txn().unwrap();
+        tree.rebuild(&mut wtxn, &leaves).unwrap();
+        assert_eq!(merkle_root(&leaves), tree.root(&wtxn).unwrap());
+        assert_eq!(3, tree.version(&wtxn).unwrap());
+    }
+}
@@ -1,55 +1,165 @@
-use std::io::ErrorKind;
-use anyhow::Error;
-use fastembed::{EmbeddingModel, InitOptions, TextEmbedding};
-use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::Path;
+use anyhow::Context;
+use sha2::{Digest, Sha256};
+use fastembed::{
+    EmbeddingModel, InitOptions, InitOptionsUserDefined, Pooling, TextEmbedding, TokenizerFiles,
+    UserDefinedEmbeddingModel,
+};
 use crate::services::simple_db_nn::Embeddable;
 
-#[derive(Serialize, Deserialize)]
-pub struct DocumentEntry {
-    pub content: String,
-}
+/// Files a local model directory must contain, as exported by `optimum-cli export onnx`.
+pub const ONNX_FILE: &str = "model.onnx";
+pub const TOKENIZER_FILE: &str = "tokenizer.json";
+pub const CONFIG_FILE: &str = "config.json";
+pub const SPECIAL_TOKENS_MAP_FILE: &str = "special_tokens_map.json";
+pub const TOKENIZER_CONFIG_FILE: &str = "tokenizer_config.json";
 
-impl DocumentEntry {
-    pub fn new(content: &str) -> Self {
-        Self { content: content.to_string() }
-    }
-}
+/// fastembed's own default.
+pub const DEFAULT_BATCH_SIZE: usize = 256;
 
+/// The embedding service of the server, shared by the store and the search handlers so
+/// the query vector of the ANN lookup is also the one proven.
 pub struct ModelEmbed{
     model: TextEmbedding,
+    /// fastembed model code, or the id of a local model.
+    name: String,
+    /// `name`, and the passage prefix when there is one, as stored vectors depend on both.
+    model_id: String,
+    dimensions: usize,
+    /// Documents per ONNX inference in [`Embeddable::to_embeddings`].
+    batch_size: usize,
+    /// Prepended to queries, e.g. `query: ` for E5 models.
+    query_prefix: String,
+    /// Prepended to stored documents, e.g. `passage: ` for E5 models.
+    passage_prefix: String,
 }
 
-impl Default for ModelEmbed {
-    fn default() -> Self {
-        ModelEmbed::new()
+impl ModelEmbed {
+    /// Downloads the default model on first use.
+    #[cfg(test)]
+    pub fn new() -> anyhow::Result<Self> {
+        ModelEmbed::with_model(EmbeddingModel::AllMiniLML6V2)
     }
-}
 
-impl ModelEmbed {
-    pub fn new() -> Self {
-        let model = TextEmbedding::try_new(InitOptions::new(EmbeddingModel::AllMiniLML6V2)).expect("Failed to create Embedding");
-        ModelEmbed{model}
+    /// A fastembed model, downloaded to the fastembed cache on first use.
+    pub fn with_model(model: EmbeddingModel) -> anyhow::Result<Self> {
+        let (model_id, dimensions) = model_code(&model)?;
+        let model = TextEmbedding::try_new(InitOptions::new(model))?;
+        Ok(ModelEmbed::from_parts(model, model_id, dimensions))
+    }
+
+    /// An ONNX model and its tokenizer read from `dir`, without any network access.
+    /// The model is recorded in the store as `model_id` and a digest of its weights, see
+    /// [`local_model_id`].
+    pub fn from_dir(dir: &Path, model_id: &str, pooling: Pooling) -> anyhow::Result<Self> {
+        let read = |name: &str| {
+            let path = dir.join(name);
+            fs::read(&path).with_context(|| format!("Can not read {:?} of the local model", path))
+        };
+        let tokenizer_files = TokenizerFiles {
+            tokenizer_file: read(TOKENIZER_FILE)?,
+            config_file: read(CONFIG_FILE)?,
+            special_tokens_map_file: read(SPECIAL_TOKENS_MAP_FILE)?,
+            tokenizer_config_file: read(TOKENIZER_CONFIG_FILE)?,
+        };
+        let onnx = read(ONNX_FILE)?;
+        let model_id = local_model_id(model_id, &onnx);
+        let model = UserDefinedEmbeddingModel::new(onnx, tokenizer_files).with_pooling(pooling);
+        let model = TextEmbedding::try_new_from_user_defined(model, InitOptionsUserDefined::default())
+            .with_context(|| format!("Can not load the local model in {:?}", dir))?;
+        // the files do not state the output size, so it is measured once
+        let dimensions = model
+            .embed(vec!["dimensions"], None)?
+            .first()
+            .map(Vec::len)
+            .ok_or_else(|| anyhow::anyhow!("The local model in {:?} returned no embedding", dir))?;
+        Ok(ModelEmbed::from_parts(model, model_id, dimensions))
+    }
+
+    fn from_parts(model: TextEmbedding, model_id: String, dimensions: usize) -> Self {
+        ModelEmbed {
+            model,
+            name: model_id.clone(),
+            model_id,
+            dimensions,
+            batch_size: DEFAULT_BATCH_SIZE,
+            query_prefix: String::new(),
+            passage_prefix: String::new(),
+        }
+    }
+
+    pub fn with_batch_size(self, batch_size: usize) -> Self {
+        ModelEmbed { batch_size: batch_size.max(1), ..self }
+    }
+
+    /// The passage prefix becomes part of the model id, so collections embedded with
+    /// another prefix are refused when opened.
+    pub fn with_prefixes(self, query_prefix: &str, passage_prefix: &str) -> Self {
+        let model_id = match passage_prefix {
+            "" => self.name.clone(),
+            prefix => format!("{}+passage={:?}", self.name, prefix),
+        };
+        ModelEmbed {
+            model_id,
+            query_prefix: query_prefix.to_string(),
+            passage_prefix: passage_prefix.to_string(),
+            ..self
+        }
     }
 
-    pub fn calculate_one_embed(&self, document_entry: DocumentEntry) -> anyhow::Result<Vec<f32>> {
-        let batch = vec![serde_json::to_string(&document_entry)?];
-        let binding =  self.model.embed(batch, None)?;
-        let embedding =binding.first().expect("It can not calculate the embedding");
-        Ok(embedding.clone())
+    fn embed_with_prefix(&self, prefix: &str, contents: &[&[u8]]) -> anyhow::Result<Vec<Vec<f32>>> {
+        let batch = contents
+            .iter()
+            .map(|content| std::str::from_utf8(content).map(|text| format!("{}{}", prefix, text)))
+            .collect::<Result<Vec<String>, _>>()
+            .context("Content is not valid UTF-8")?;
+        if batch.is_empty() {
+            return Ok(Vec::new());
+        }
+        self.model.embed(batch, Some(self.batch_size))
     }
 }
 
+/// The id a fastembed model is recorded with, its model code as fastembed spells it
+/// (e.g. `Qdrant/all-MiniLM-L6-v2-onnx`) whatever the case it was configured in, and its
+/// dimensions.
+pub fn model_code(model: &EmbeddingModel) -> anyhow::Result<(String, usize)> {
+    let info = TextEmbedding::get_model_info(model)?;
+    Ok((info.model_code.clone(), info.dim))
+}
+
+/// `name@` and the first 8 bytes of the sha256 of the ONNX file, so two local models
+/// given the same name still record different ids.
+pub fn local_model_id(name: &str, onnx: &[u8]) -> String {
+    format!("{}@{}", name, hex::encode(&Sha256::digest(onnx)[..8]))
+}
+
 impl Embeddable for ModelEmbed {
-    fn to_embedding(&self, content: Vec<u8>) -> Vec<f32> {
-        let batch = vec![String::from_utf8(content).expect("Failed to convert content to string")];
-        let binding =  self.model.embed(batch, None).expect("Failed to get embedding");
-        let embedding =binding.first().expect("It can not calculate the embedding");
-        embedding.to_vec()
+    fn to_embedding(&self, content: &[u8]) -> anyhow::Result<Vec<f32>> {
+        self.to_embeddings(&[content])?
+            .pop()
+            .ok_or_else(|| anyhow::anyhow!("The model returned no embedding"))
     }
-}
 
+    fn to_query_embedding(&self, query: &[u8]) -> anyhow::Result<Vec<f32>> {
+        self.embed_with_prefix(&self.query_prefix, &[query])?
+            .pop()
+            .ok_or_else(|| anyhow::anyhow!("The model returned no embedding"))
+    }
 
+    fn to_embeddings(&self, contents: &[&[u8]]) -> anyhow::Result<Vec<Vec<f32>>> {
+        self.embed_with_prefix(&self.passage_prefix, contents)
+    }
 
+    fn dimensions(&self) -> usize {
+        self.dimensions
+    }
+
+    fn model_id(&self) -> &str {
+        &self.model_id
+    }
+}
 
 #[cfg(test)]
 mod tests {
@@ -57,8 +167,49 @@ mod tests {
 
     #[test]
     fn check_calculate_embedding() {
-        let model = ModelEmbed::new();
-        let results = model.calculate_one_embed(DocumentEntry::new( "encoding")).unwrap();
+        let model = ModelEmbed::new().unwrap();
+        assert_eq!("Qdrant/all-MiniLM-L6-v2-onnx", model.model_id());
+        assert_eq!(384, model.dimensions());
+        let results = model.to_query_embedding(b"encoding").unwrap();
         println!("{:?}", results);
+        // without prefixes queries and passages are embedded alike
+        assert_eq!(results, model.to_embedding(b"encoding").unwrap());
+
+        let model = model.with_batch_size(2);
+        let contents: [&[u8]; 3] = [b"one", b"two", b"three"];
+        let embeddings = model.to_embeddings(&contents).unwrap();
+        assert_eq!(3, embeddings.len());
+        assert_eq!(model.to_embedding(b"three").unwrap(), embeddings[2]);
+        assert!(model.to_embeddings(&[&[0xff]]).is_err());
+        assert!(model.to_embedding(&[0xff]).is_err());
+
+        let model = model.with_prefixes("query: ", "passage: ");
+        assert_ne!(model.to_query_embedding(b"encoding").unwrap(), model.to_embedding(b"encoding").unwrap());
+        assert_eq!("Qdrant/all-MiniLM-L6-v2-onnx+passage=\"passage: \"", model.model_id());
+        // only the passage prefix changes the stored vectors
+        let model = model.with_prefixes("query: ", "");
+        assert_eq!("Qdrant/all-MiniLM-L6-v2-onnx", model.model_id());
+    }
+
+    #[test]
+    fn model_code_test() {
+        let model = "qdrant/all-minilm-l6-v2-onnx".parse::<EmbeddingModel>().unwrap();
+        assert_eq!(EmbeddingModel::AllMiniLML6V2, model);
+        assert_eq!(("Qdrant/all-MiniLM-L6-v2-onnx".to_string(), 384), model_code(&model).unwrap());
+    }
+
+    #[test]
+    fn missing_local_model_test() {
+        let dir = tempfile::tempdir().unwrap();
+        let err = ModelEmbed::from_dir(dir.path(), "local", Pooling::Mean).err().unwrap();
+        assert!(err.to_string().contains(TOKENIZER_FILE));
+    }
+
+    #[test]
+    fn local_model_id_test() {
+        let id = local_model_id("local", b"weights");
+        assert!(id.starts_with("local@"));
+        assert_eq!(id, local_model_id("local", b"weights"));
+        assert_ne!(id, local_model_id("local", b"other weights"));
     }
  }
\ No newline at end of file
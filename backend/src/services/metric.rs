@@ -0,0 +1,110 @@
+use std::fmt;
+use std::str::FromStr;
+
+use docsim_common::ranking::RankMetric;
+use serde::{Deserialize, Serialize};
+
+/// Distance used by the arroy index of a collection.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum Metric {
+    Cosine,
+    /// Collections created before metrics were configurable are Euclidean.
+    #[default]
+    Euclidean,
+    DotProduct,
+    Manhattan,
+    BinaryQuantizedCosine,
+    BinaryQuantizedEuclidean,
+    BinaryQuantizedManhattan,
+}
+
+impl Metric {
+    pub const ALL: [Metric; 7] = [
+        Metric::Cosine,
+        Metric::Euclidean,
+        Metric::DotProduct,
+        Metric::Manhattan,
+        Metric::BinaryQuantizedCosine,
+        Metric::BinaryQuantizedEuclidean,
+        Metric::BinaryQuantizedManhattan,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Metric::Cosine => "cosine",
+            Metric::Euclidean => "euclidean",
+            Metric::DotProduct => "dot_product",
+            Metric::Manhattan => "manhattan",
+            Metric::BinaryQuantizedCosine => "binary_quantized_cosine",
+            Metric::BinaryQuantizedEuclidean => "binary_quantized_euclidean",
+            Metric::BinaryQuantizedManhattan => "binary_quantized_manhattan",
+        }
+    }
+
+    /// The same metric as evaluated by the ranking guest, which has no binary quantized variants.
+    pub fn rank_metric(&self) -> Option<RankMetric> {
+        match self {
+            Metric::Cosine => Some(RankMetric::Cosine),
+            Metric::Euclidean => Some(RankMetric::Euclidean),
+            Metric::DotProduct => Some(RankMetric::DotProduct),
+            Metric::Manhattan => Some(RankMetric::Manhattan),
+            Metric::BinaryQuantizedCosine | Metric::BinaryQuantizedEuclidean | Metric::BinaryQuantizedManhattan => None,
+        }
+    }
+}
+
+impl fmt::Display for Metric {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+impl FromStr for Metric {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Metric::ALL
+            .into_iter()
+            .find(|metric| metric.name().eq_ignore_ascii_case(s))
+            .ok_or_else(|| format!("Unknown metric: {s}"))
+    }
+}
+
+/// Evaluates `$body` with `$D` bound to the arroy distance type of `$metric`.
+macro_rules! with_distance {
+    ($metric:expr, $D:ident => $body:expr) => {
+        match $metric {
+            $crate::services::metric::Metric::Cosine => {
+                type $D = arroy::distances::Cosine;
+                $body
+            }
+            $crate::services::metric::Metric::Euclidean => {
+                type $D = arroy::distances::Euclidean;
+                $body
+            }
+            $crate::services::metric::Metric::DotProduct => {
+                type $D = arroy::distances::DotProduct;
+                $body
+            }
+            $crate::services::metric::Metric::Manhattan => {
+                type $D = arroy::distances::Manhattan;
+                $body
+            }
+            $crate::services::metric::Metric::BinaryQuantizedCosine => {
+                type $D = arroy::distances::BinaryQuantizedCosine;
+                $body
+            }
+            $crate::services::metric::Metric::BinaryQuantizedEuclidean => {
+                type $D = arroy::distances::BinaryQuantizedEuclidean;
+                $body
+            }
+            $crate::services::metric::Metric::BinaryQuantizedManhattan => {
+                type $D = arroy::distances::BinaryQuantizedManhattan;
+                $body
+            }
+        }
+    };
+}
+
+pub(crate) use with_distance;
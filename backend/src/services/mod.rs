@@ -1,2 +1,7 @@
 pub mod simple_db_nn;
-pub mod embed;
\ No newline at end of file
+pub mod embed;
+pub mod snapshot;
+pub mod metric;
+pub mod proofs;
+pub mod receipts;
+pub mod corpus_tree;
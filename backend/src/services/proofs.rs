@@ -0,0 +1,402 @@
+use std::collections::{HashMap, VecDeque};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+
+use host::{
+    document_digest, BatchJournal, BatchSimilarityInput, FixedJournal, FixedSimilarityInput, RankingInput, RankingJournal,
+    SimilarityJournal,
+};
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+use tokio::sync::mpsc;
+use tracing::log::error;
+
+use crate::services::receipts::{receipt_key, ReceiptKey, ReceiptStore};
+
+pub type JobId = u64;
+
+/// Produces a serialized receipt, [`ProofInput::prove`] outside of tests.
+pub type Prover = Arc<dyn Fn(&ProofInput) -> anyhow::Result<Vec<u8>> + Send + Sync>;
+
+/// What a job proves, one variant per guest that searches submit to.
+#[derive(Clone, Debug, PartialEq)]
+pub enum ProofInput {
+    /// Similarity of a query to every result of a search, in one receipt.
+    BatchSimilarity(BatchSimilarityInput),
+    /// The same, with quantized embeddings and integer arithmetic.
+    FixedSimilarity(FixedSimilarityInput),
+    /// Exact top-k of a query over a whole collection.
+    Ranking(RankingInput),
+}
+
+/// The guest a job runs, which fixes the image id and the journal type of its receipt.
+#[derive(Serialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum Guest {
+    Similarity,
+    BatchSimilarity,
+    FixedSimilarity,
+    Ranking,
+}
+
+/// A decoded journal, serialized as the journal struct itself.
+#[derive(Serialize, Clone, Debug, PartialEq)]
+#[serde(untagged)]
+pub enum Journal {
+    Similarity(SimilarityJournal),
+    BatchSimilarity(BatchJournal),
+    FixedSimilarity(FixedJournal),
+    Ranking(RankingJournal),
+}
+
+impl Guest {
+    /// Id of the guest image the receipt is verified against.
+    pub fn image_id(&self) -> [u32; 8] {
+        match self {
+            Guest::Similarity => host::image_id(),
+            Guest::BatchSimilarity => host::batch_image_id(),
+            Guest::FixedSimilarity => host::fixed_image_id(),
+            Guest::Ranking => host::ranking_image_id(),
+        }
+    }
+
+    pub fn decode_journal(&self, receipt: &[u8]) -> anyhow::Result<Journal> {
+        Ok(match self {
+            Guest::Similarity => Journal::Similarity(host::decode_journal(receipt)?),
+            Guest::BatchSimilarity => Journal::BatchSimilarity(host::decode_journal(receipt)?),
+            Guest::FixedSimilarity => Journal::FixedSimilarity(host::decode_journal(receipt)?),
+            Guest::Ranking => Journal::Ranking(host::decode_journal(receipt)?),
+        })
+    }
+}
+
+impl Journal {
+    /// The similarity the receipt attests, for the similarity guests. `index` picks the
+    /// result of a batch journal, the other journals have a single one.
+    pub fn proven_similarity(&self, index: usize) -> Option<f32> {
+        match self {
+            Journal::Similarity(journal) => Some(journal.similarity),
+            Journal::BatchSimilarity(journal) => journal.results.get(index).map(|result| result.similarity),
+            Journal::FixedSimilarity(journal) => Some(journal.similarity.cosine() as f32),
+            Journal::Ranking(_) => None,
+        }
+    }
+
+    pub fn above_threshold(&self, index: usize) -> Option<bool> {
+        match self {
+            Journal::Similarity(journal) => Some(journal.above_threshold),
+            Journal::BatchSimilarity(journal) => journal.results.get(index).map(|result| result.above_threshold),
+            Journal::FixedSimilarity(journal) => Some(journal.above_threshold),
+            Journal::Ranking(_) => None,
+        }
+    }
+}
+
+impl ProofInput {
+    pub fn prove(&self) -> anyhow::Result<Vec<u8>> {
+        match self {
+            ProofInput::BatchSimilarity(input) => host::execute_and_serialize_batch_receipt(input),
+            ProofInput::FixedSimilarity(input) => host::execute_and_serialize_fixed_receipt(input),
+            ProofInput::Ranking(input) => host::execute_and_serialize_ranking_receipt(input),
+        }
+    }
+
+    pub fn guest(&self) -> Guest {
+        match self {
+            ProofInput::BatchSimilarity(_) => Guest::BatchSimilarity,
+            ProofInput::FixedSimilarity(_) => Guest::FixedSimilarity,
+            ProofInput::Ranking(_) => Guest::Ranking,
+        }
+    }
+
+    pub fn receipt_key(&self) -> ReceiptKey {
+        let mut hasher = Sha256::new();
+        match self {
+            ProofInput::BatchSimilarity(input) => {
+                hasher.update((input.query.len() as u64).to_le_bytes());
+                for value in &input.query {
+                    hasher.update(value.to_le_bytes());
+                }
+                hasher.update((input.candidates.len() as u64).to_le_bytes());
+                for candidate in &input.candidates {
+                    hasher.update((candidate.embedding.len() as u64).to_le_bytes());
+                    for value in &candidate.embedding {
+                        hasher.update(value.to_le_bytes());
+                    }
+                    hasher.update(document_digest(candidate.id, &candidate.content));
+                }
+                hasher.update(input.threshold.to_le_bytes());
+            }
+            ProofInput::FixedSimilarity(input) => {
+                hasher.update((input.query.len() as u64).to_le_bytes());
+                for value in input.query.iter().chain(&input.document) {
+                    hasher.update(value.to_le_bytes());
+                }
+                hasher.update(document_digest(input.id, &input.content));
+                hasher.update(input.threshold.to_le_bytes());
+            }
+            ProofInput::Ranking(input) => {
+                for value in &input.query {
+                    hasher.update(value.to_le_bytes());
+                }
+                hasher.update(input.k.to_le_bytes());
+                hasher.update([input.metric as u8]);
+                hasher.update(input.root());
+            }
+        }
+        receipt_key(self.guest().image_id(), &hasher.finalize().into())
+    }
+}
+
+#[derive(Serialize, Clone, Debug, PartialEq)]
+#[serde(tag = "state", rename_all = "snake_case")]
+pub enum JobState {
+    Queued,
+    Running,
+    Done { receipt: Vec<u8> },
+    Failed { error: String },
+}
+
+impl JobState {
+    pub fn is_finished(&self) -> bool {
+        matches!(self, JobState::Done { .. } | JobState::Failed { .. })
+    }
+}
+
+struct ProofRequest {
+    id: JobId,
+    input: ProofInput,
+}
+
+#[derive(Default)]
+struct Jobs {
+    states: HashMap<JobId, JobState>,
+    /// Guest each job runs, kept as long as its state.
+    guests: HashMap<JobId, Guest>,
+    /// Finished jobs, oldest first, dropped once more than `max_finished` are kept.
+    finished: VecDeque<JobId>,
+}
+
+/// Receipts are generated by `workers` background tasks, so a search only enqueues jobs.
+/// Pairs found in the receipt store finish on submission without reaching a worker.
+pub struct ProofQueue {
+    jobs: Mutex<Jobs>,
+    receipts: Option<Arc<ReceiptStore>>,
+    next_id: AtomicU64,
+    sender: mpsc::Sender<ProofRequest>,
+    max_finished: usize,
+}
+
+impl ProofQueue {
+    /// Spawns the workers on the current tokio runtime. At most `capacity` jobs wait in the queue.
+    pub fn start(
+        prover: Prover,
+        receipts: Option<Arc<ReceiptStore>>,
+        workers: usize,
+        capacity: usize,
+        max_finished: usize,
+    ) -> Arc<Self> {
+        let (sender, receiver) = mpsc::channel(capacity);
+        let queue = Arc::new(ProofQueue {
+            jobs: Mutex::new(Jobs::default()),
+            receipts,
+            next_id: AtomicU64::new(0),
+            sender,
+            max_finished,
+        });
+        let receiver = Arc::new(tokio::sync::Mutex::new(receiver));
+        for _ in 0..workers.max(1) {
+            let queue = queue.clone();
+            let receiver = receiver.clone();
+            let prover = prover.clone();
+            tokio::spawn(async move {
+                loop {
+                    let Some(request) = receiver.lock().await.recv().await else {
+                        break;
+                    };
+                    queue.set_state(request.id, JobState::Running);
+                    let prover = prover.clone();
+                    let receipts = queue.receipts.clone();
+                    let proved = tokio::task::spawn_blocking(move || {
+                        let receipt = prover(&request.input)?;
+                        if let Some(receipts) = receipts
+                            && let Err(err) = receipts.put(&request.input.receipt_key(), &receipt)
+                        {
+                            error!("Err={:?}", err.to_string());
+                        }
+                        Ok::<_, anyhow::Error>(receipt)
+                    })
+                    .await;
+                    let state = match proved {
+                        Ok(Ok(receipt)) => JobState::Done { receipt },
+                        Ok(Err(err)) => JobState::Failed { error: err.to_string() },
+                        Err(err) => JobState::Failed { error: format!("Prover panicked: {}", err) },
+                    };
+                    queue.set_state(request.id, state);
+                }
+            });
+        }
+        queue
+    }
+
+    /// Enqueues a proof, or returns `None` when the queue is full.
+    pub fn submit(&self, input: ProofInput) -> Option<JobId> {
+        self.submit_all(vec![input])?.pop()
+    }
+
+    /// Enqueues every proof, or none of them when the queue can not take them all.
+    pub fn submit_all(&self, inputs: Vec<ProofInput>) -> Option<Vec<JobId>> {
+        let cached = inputs.iter().map(|input| self.cached(input)).collect::<Vec<Option<Vec<u8>>>>();
+        let uncached = cached.iter().filter(|receipt| receipt.is_none()).count();
+        // the slots are reserved up front, so a full queue leaves no job behind
+        let mut permits = match uncached {
+            0 => None,
+            uncached => Some(self.sender.try_reserve_many(uncached).ok()?),
+        };
+        let mut ids = Vec::with_capacity(inputs.len());
+        for (input, receipt) in inputs.into_iter().zip(cached) {
+            let id = self.next_id.fetch_add(1, Ordering::Relaxed);
+            self.jobs.lock().unwrap_or_else(|err| err.into_inner()).guests.insert(id, input.guest());
+            match receipt {
+                Some(receipt) => self.set_state(id, JobState::Done { receipt }),
+                None => {
+                    self.set_state(id, JobState::Queued);
+                    let permit = permits.as_mut().and_then(Iterator::next).expect("one permit per uncached input");
+                    permit.send(ProofRequest { id, input });
+                }
+            }
+            ids.push(id);
+        }
+        Some(ids)
+    }
+
+    /// Looks the receipt up in a read transaction, the LRU stamp is written on a blocking
+    /// thread so searches do not wait for the write lock of the environment.
+    fn cached(&self, input: &ProofInput) -> Option<Vec<u8>> {
+        let receipts = self.receipts.clone()?;
+        let key = input.receipt_key();
+        let receipt = receipts.get(&key).unwrap_or_else(|err| {
+            error!("Err={:?}", err.to_string());
+            None
+        })?;
+        tokio::task::spawn_blocking(move || {
+            if let Err(err) = receipts.touch(&key) {
+                error!("Err={:?}", err.to_string());
+            }
+        });
+        Some(receipt)
+    }
+
+    pub fn get(&self, id: JobId) -> Option<JobState> {
+        self.jobs.lock().unwrap_or_else(|err| err.into_inner()).states.get(&id).cloned()
+    }
+
+    pub fn guest(&self, id: JobId) -> Option<Guest> {
+        self.jobs.lock().unwrap_or_else(|err| err.into_inner()).guests.get(&id).copied()
+    }
+
+    fn set_state(&self, id: JobId, state: JobState) {
+        let mut jobs = self.jobs.lock().unwrap_or_else(|err| err.into_inner());
+        if state.is_finished() {
+            jobs.finished.push_back(id);
+            while jobs.finished.len() > self.max_finished {
+                if let Some(evicted) = jobs.finished.pop_front() {
+                    jobs.states.remove(&evicted);
+                    jobs.guests.remove(&evicted);
+                }
+            }
+        }
+        jobs.states.insert(id, state);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    fn input(query: Vec<f32>, document: Vec<f32>) -> ProofInput {
+        ProofInput::BatchSimilarity(BatchSimilarityInput {
+            query,
+            candidates: vec![host::BatchCandidate { embedding: document, id: 0, content: String::new() }],
+            threshold: host::DEFAULT_THRESHOLD,
+        })
+    }
+
+    async fn wait_finished(queue: &ProofQueue, id: JobId) -> JobState {
+        loop {
+            match queue.get(id) {
+                Some(state) if state.is_finished() => return state,
+                _ => tokio::time::sleep(Duration::from_millis(10)).await,
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn proof_queue_test() {
+        let prover: Prover = Arc::new(|input| match input {
+            ProofInput::BatchSimilarity(input) if !input.candidates[0].embedding.is_empty() => {
+                Ok(vec![input.query.len() as u8, input.candidates[0].embedding.len() as u8])
+            }
+            _ => anyhow::bail!("empty document"),
+        });
+        let queue = ProofQueue::start(prover, None, 2, 16, 16);
+
+        let done = queue.submit(input(vec![1.; 3], vec![1.; 4])).unwrap();
+        let failed = queue.submit(input(vec![1.; 3], vec![])).unwrap();
+        assert_eq!(JobState::Done { receipt: vec![3, 4] }, wait_finished(&queue, done).await);
+        assert_eq!(JobState::Failed { error: "empty document".to_string() }, wait_finished(&queue, failed).await);
+        assert_eq!(None, queue.get(failed + 1));
+        assert_eq!(Some(Guest::BatchSimilarity), queue.guest(done));
+    }
+
+    #[tokio::test]
+    async fn proof_queue_full_test() {
+        let prover: Prover = Arc::new(|_| Ok(vec![]));
+        let queue = ProofQueue::start(prover, None, 1, 2, 16);
+        let inputs = (0..3).map(|i| input(vec![i as f32], vec![1.])).collect::<Vec<ProofInput>>();
+        assert_eq!(None, queue.submit_all(inputs.clone()));
+        // nothing of the refused request was queued
+        assert_eq!(None, queue.get(0));
+        let ids = queue.submit_all(inputs[..2].to_vec()).unwrap();
+        assert_eq!(2, ids.len());
+        wait_finished(&queue, ids[1]).await;
+    }
+
+    #[tokio::test]
+    async fn proof_queue_eviction_test() {
+        let prover: Prover = Arc::new(|_| Ok(vec![]));
+        let queue = ProofQueue::start(prover, None, 1, 16, 2);
+        let ids = (0..3).map(|_| queue.submit(input(vec![], vec![1.])).unwrap()).collect::<Vec<JobId>>();
+        wait_finished(&queue, ids[2]).await;
+        assert_eq!(None, queue.get(ids[0]));
+        assert_eq!(None, queue.guest(ids[0]));
+        assert!(queue.get(ids[1]).is_some());
+    }
+
+    #[tokio::test]
+    async fn proof_queue_cache_test() {
+        let dir = tempfile::tempdir().unwrap();
+        let receipts = Arc::new(ReceiptStore::open(dir.path(), 16, 1024).unwrap());
+        let calls = Arc::new(AtomicU64::new(0));
+        let counter = calls.clone();
+        let prover: Prover = Arc::new(move |_| {
+            counter.fetch_add(1, Ordering::Relaxed);
+            Ok(vec![7])
+        });
+        let queue = ProofQueue::start(prover, Some(receipts), 1, 16, 16);
+
+        let first = queue.submit(input(vec![1.], vec![2.])).unwrap();
+        assert_eq!(JobState::Done { receipt: vec![7] }, wait_finished(&queue, first).await);
+        let second = queue.submit(input(vec![1.], vec![2.])).unwrap();
+        assert_eq!(Some(JobState::Done { receipt: vec![7] }), queue.get(second));
+        assert_eq!(1, calls.load(Ordering::Relaxed));
+
+        // the threshold is part of the proven statement
+        let ProofInput::BatchSimilarity(mut stricter) = input(vec![1.], vec![2.]) else { unreachable!() };
+        stricter.threshold = 0.95;
+        let third = queue.submit(ProofInput::BatchSimilarity(stricter)).unwrap();
+        wait_finished(&queue, third).await;
+        assert_eq!(2, calls.load(Ordering::Relaxed));
+    }
+}
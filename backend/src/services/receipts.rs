@@ -0,0 +1,198 @@
+use byteorder::BigEndian;
+use heed::types::{Bytes, Str, Unit, U64};
+use heed::{Database as HeedDatabase, Env, EnvOpenOptions, RwTxn};
+use sha2::{Digest, Sha256};
+use std::fs;
+use std::path::Path;
+
+/// Only reserves address space, the limits of the store bound the size of the file.
+const MAP_SIZE: usize = 1024 * 1024 * 1024 * 200;
+
+const RECEIPTS_DB: &str = "receipts";
+const RECEIPTS_LRU_DB: &str = "receipts-lru";
+const RECEIPTS_META_DB: &str = "receipts-meta";
+const CLOCK_KEY: &str = "clock";
+const TOTAL_BYTES_KEY: &str = "total-bytes";
+/// Every stored receipt is prefixed with the big endian clock value of its last use.
+const STAMP_LEN: usize = 8;
+
+pub type ReceiptKey = [u8; 32];
+
+/// Receipts already proven, stored in an environment of their own so that snapshots of the
+/// documents never carry them.
+///
+/// Entries are keyed by [`receipt_key`], so a new guest never serves receipts of the previous
+/// one. The least recently used entries are evicted once `max_entries` or `max_bytes` is exceeded.
+pub struct ReceiptStore {
+    env: Env,
+    receipts: HeedDatabase<Bytes, Bytes>,
+    /// `stamp | key`, ordered from least to most recently used.
+    lru: HeedDatabase<Bytes, Unit>,
+    meta: HeedDatabase<Str, U64<BigEndian>>,
+    max_entries: u64,
+    max_bytes: u64,
+}
+
+/// sha256 of the guest image id and of a digest of the whole guest input.
+pub fn receipt_key(image_id: [u32; 8], input_digest: &[u8; 32]) -> ReceiptKey {
+    let mut hasher = Sha256::new();
+    for word in image_id {
+        hasher.update(word.to_le_bytes());
+    }
+    hasher.update(input_digest);
+    hasher.finalize().into()
+}
+
+fn lru_key(stamp: &[u8], key: &[u8]) -> Vec<u8> {
+    [stamp, key].concat()
+}
+
+fn stamped(stamp: u64, receipt: &[u8]) -> Vec<u8> {
+    [&stamp.to_be_bytes()[..], receipt].concat()
+}
+
+impl ReceiptStore {
+    /// Opens the environment in `dir`. A store with `max_entries == 0` keeps nothing.
+    pub fn open(dir: &Path, max_entries: u64, max_bytes: u64) -> anyhow::Result<Self> {
+        fs::create_dir_all(dir)?;
+        let env = unsafe { EnvOpenOptions::new().map_size(MAP_SIZE).max_dbs(3).open(dir) }?;
+        let mut wtxn = env.write_txn()?;
+        let receipts = env.create_database(&mut wtxn, Some(RECEIPTS_DB))?;
+        let lru = env.create_database(&mut wtxn, Some(RECEIPTS_LRU_DB))?;
+        let meta = env.create_database(&mut wtxn, Some(RECEIPTS_META_DB))?;
+        wtxn.commit()?;
+        let store = ReceiptStore { env, receipts, lru, meta, max_entries, max_bytes };
+        // limits may have been lowered since the last run
+        let mut wtxn = store.env.write_txn()?;
+        let total = store.meta.get(&wtxn, TOTAL_BYTES_KEY)?.unwrap_or(0);
+        store.evict(&mut wtxn, total)?;
+        wtxn.commit()?;
+        Ok(store)
+    }
+
+    /// The cached receipt, read without blocking writers. Callers [`ReceiptStore::touch`]
+    /// the entry afterwards to keep it from being evicted.
+    pub fn get(&self, key: &ReceiptKey) -> anyhow::Result<Option<Vec<u8>>> {
+        if self.max_entries == 0 {
+            return Ok(None);
+        }
+        let rtxn = self.env.read_txn()?;
+        Ok(self.receipts.get(&rtxn, key)?.map(|value| value[STAMP_LEN..].to_vec()))
+    }
+
+    /// Makes the entry the most recently used one, if it is still cached.
+    pub fn touch(&self, key: &ReceiptKey) -> anyhow::Result<()> {
+        if self.max_entries == 0 {
+            return Ok(());
+        }
+        let mut wtxn = self.env.write_txn()?;
+        let Some(value) = self.receipts.get(&wtxn, key)?.map(<[u8]>::to_vec) else {
+            return Ok(());
+        };
+        let (old_stamp, receipt) = value.split_at(STAMP_LEN);
+        let stamp = self.tick(&mut wtxn)?;
+        self.lru.delete(&mut wtxn, &lru_key(old_stamp, key))?;
+        self.lru.put(&mut wtxn, &lru_key(&stamp.to_be_bytes(), key), &())?;
+        self.receipts.put(&mut wtxn, key, &stamped(stamp, receipt))?;
+        wtxn.commit()?;
+        Ok(())
+    }
+
+    pub fn put(&self, key: &ReceiptKey, receipt: &[u8]) -> anyhow::Result<()> {
+        if self.max_entries == 0 || receipt.len() as u64 > self.max_bytes {
+            return Ok(());
+        }
+        let mut wtxn = self.env.write_txn()?;
+        let mut total = self.meta.get(&wtxn, TOTAL_BYTES_KEY)?.unwrap_or(0);
+        if let Some(old) = self.receipts.get(&wtxn, key)?.map(<[u8]>::to_vec) {
+            let (old_stamp, old_receipt) = old.split_at(STAMP_LEN);
+            self.lru.delete(&mut wtxn, &lru_key(old_stamp, key))?;
+            total -= old_receipt.len() as u64;
+        }
+        let stamp = self.tick(&mut wtxn)?;
+        self.receipts.put(&mut wtxn, key, &stamped(stamp, receipt))?;
+        self.lru.put(&mut wtxn, &lru_key(&stamp.to_be_bytes(), key), &())?;
+        self.evict(&mut wtxn, total + receipt.len() as u64)?;
+        wtxn.commit()?;
+        Ok(())
+    }
+
+    /// Number of cached receipts and their total size in bytes.
+    pub fn usage(&self) -> anyhow::Result<(u64, u64)> {
+        let rtxn = self.env.read_txn()?;
+        Ok((self.receipts.len(&rtxn)?, self.meta.get(&rtxn, TOTAL_BYTES_KEY)?.unwrap_or(0)))
+    }
+
+    fn tick(&self, wtxn: &mut RwTxn) -> anyhow::Result<u64> {
+        let clock = self.meta.get(wtxn, CLOCK_KEY)?.unwrap_or(0) + 1;
+        self.meta.put(wtxn, CLOCK_KEY, &clock)?;
+        Ok(clock)
+    }
+
+    /// Drops the least recently used receipts until both limits hold, then records `total`.
+    fn evict(&self, wtxn: &mut RwTxn, mut total: u64) -> anyhow::Result<()> {
+        while self.receipts.len(wtxn)? > self.max_entries || total > self.max_bytes {
+            let Some(oldest) = self.lru.first(wtxn)?.map(|(oldest, ())| oldest.to_vec()) else {
+                break;
+            };
+            let key = &oldest[STAMP_LEN..];
+            if let Some(len) = self.receipts.get(wtxn, key)?.map(<[u8]>::len) {
+                total -= (len - STAMP_LEN) as u64;
+            }
+            self.receipts.delete(wtxn, key)?;
+            self.lru.delete(wtxn, &oldest)?;
+        }
+        self.meta.put(wtxn, TOTAL_BYTES_KEY, &total)?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn key(input: u8) -> ReceiptKey {
+        receipt_key([1; 8], &[input; 32])
+    }
+
+    #[test]
+    fn receipt_store_test() {
+        let dir = tempfile::tempdir().unwrap();
+        let store = ReceiptStore::open(dir.path(), 10, 1024).unwrap();
+
+        assert_eq!(None, store.get(&key(0)).unwrap());
+        store.put(&key(0), b"receipt").unwrap();
+        assert_eq!(Some(b"receipt".to_vec()), store.get(&key(0)).unwrap());
+        // same input proven by another guest
+        assert_eq!(None, store.get(&receipt_key([2; 8], &[0; 32])).unwrap());
+        assert_eq!((1, 7), store.usage().unwrap());
+
+        store.put(&key(0), b"other").unwrap();
+        assert_eq!((1, 5), store.usage().unwrap());
+    }
+
+    #[test]
+    fn receipt_store_eviction_test() {
+        let dir = tempfile::tempdir().unwrap();
+        let store = ReceiptStore::open(dir.path(), 2, 10).unwrap();
+
+        store.put(&key(0), b"aaaa").unwrap();
+        store.put(&key(1), b"bbbb").unwrap();
+        // reading alone does not change the order
+        assert!(store.get(&key(1)).unwrap().is_some());
+        // touching the first entry makes the second one the least recently used
+        store.touch(&key(0)).unwrap();
+        store.put(&key(2), b"cccc").unwrap();
+        assert_eq!(None, store.get(&key(1)).unwrap());
+        assert!(store.get(&key(0)).unwrap().is_some());
+        assert_eq!((2, 8), store.usage().unwrap());
+
+        // over the byte limit
+        store.put(&key(3), b"dddddddd").unwrap();
+        assert_eq!((1, 8), store.usage().unwrap());
+        store.put(&key(4), b"too large for the store").unwrap();
+        assert_eq!(None, store.get(&key(4)).unwrap());
+        // the entry may be evicted between the lookup and the touch
+        store.touch(&key(4)).unwrap();
+    }
+}
@@ -1,14 +1,27 @@
-use arroy::{Database as ArroyDatabase, Distance, ItemId, Reader, Writer};
-use heed::types::{Bytes, U32};
+use arroy::{Database as ArroyDatabase, Distance, Reader, Writer};
+use heed::Unspecified;
+use heed::types::{Bytes, Str, U32};
 
 use byteorder::BigEndian;
 use heed::Database as HeedDatabase;
-use heed::{Env, EnvOpenOptions};
+use heed::{Env, EnvOpenOptions, RwTxn};
 use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
 use rand::rngs::StdRng;
+use roaring::RoaringBitmap;
 use rand::SeedableRng;
+use std::collections::HashMap;
+use std::fmt;
 use std::fs;
+use std::io::{BufRead, Write};
 use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use crate::services::corpus_tree::{CorpusRoot, CorpusTree, InclusionProof};
+use crate::services::metric::{with_distance, Metric};
+use docsim_common::journal::{document_digest, Digest};
+use docsim_common::merkle::{leaf_hash, merkle_root, Hash};
+use crate::services::snapshot::{self, SnapshotManifest};
 
 type BEU32 = U32<BigEndian>;
 const DEFAULT_DIMS: usize = 384;
@@ -18,59 +31,270 @@ const MAX_DBS: u32 = 100;
 const NUM_THREADS: usize = 100;
 const INDEX_DEFAULT_NN: u16 = 0;
 const DEFAULT_SEED: u64 = 42;
+pub const DEFAULT_REBUILD_THRESHOLD: usize = 1000;
+pub const DEFAULT_REBUILD_INTERVAL: Duration = Duration::from_secs(30);
+
+pub const DEFAULT_COLLECTION: &str = "default";
+const DEFAULT_DOCUMENTS_DB: &str = "serde-bincode";
+const COLLECTIONS_DB: &str = "collections";
+const JOURNAL_DB: &str = "journal";
+const MAX_COLLECTION_NAME_LEN: usize = 64;
+/// The registry and the journal, the receipt cache lives in an environment of its own.
+const FIXED_DBS: u32 = 2;
+/// The documents and the corpus tree database of a collection.
+const DBS_PER_COLLECTION: u32 = 2;
+pub const MAX_COLLECTIONS: usize = ((MAX_DBS - FIXED_DBS) / DBS_PER_COLLECTION) as usize;
+const IMPORT_BATCH_SIZE: usize = 256;
+
+
+/// Turns document content into vectors of [`Embeddable::dimensions`] components.
+pub trait Embeddable {
+    /// Embeds a stored document, a passage.
+    fn to_embedding(&self, content: &[u8]) -> anyhow::Result<Vec<f32>>;
 
+    /// Embeds a search query. Models such as E5 embed queries and passages with different
+    /// prefixes, the default embeds both alike.
+    fn to_query_embedding(&self, query: &[u8]) -> anyhow::Result<Vec<f32>> {
+        self.to_embedding(query)
+    }
 
-pub trait Embeddable {
-    fn to_embedding(&self, _: Vec<u8>) -> Vec<f32> {
-        let values: [f32; DEFAULT_DIMS] = [0.; DEFAULT_DIMS];
-        values.to_vec()
+    /// Embeds every content, in order. Engines that can run batched inference override
+    /// this, the default embeds one content at a time.
+    fn to_embeddings(&self, contents: &[&[u8]]) -> anyhow::Result<Vec<Vec<f32>>> {
+        contents.iter().map(|content| self.to_embedding(content)).collect()
     }
+
+    fn dimensions(&self) -> usize;
+
+    /// Recorded with every collection, so vectors of different models are never mixed.
+    fn model_id(&self) -> &str;
 }
 
+/// One engine shared by the store and the code embedding queries outside of it.
+impl<T: Embeddable + ?Sized> Embeddable for Arc<T> {
+    fn to_embedding(&self, content: &[u8]) -> anyhow::Result<Vec<f32>> {
+        (**self).to_embedding(content)
+    }
 
-#[derive(Serialize, Deserialize)]
-struct Config {
-    next_id: u32,
+    fn to_query_embedding(&self, query: &[u8]) -> anyhow::Result<Vec<f32>> {
+        (**self).to_query_embedding(query)
+    }
+
+    fn to_embeddings(&self, contents: &[&[u8]]) -> anyhow::Result<Vec<Vec<f32>>> {
+        (**self).to_embeddings(contents)
+    }
+
+    fn dimensions(&self) -> usize {
+        (**self).dimensions()
+    }
+
+    fn model_id(&self) -> &str {
+        (**self).model_id()
+    }
 }
 
-impl Default for Config {
-    fn default() -> Self {
-        Config { next_id: 0 }
+/// The embedding engine failed on some content, or returned vectors that do not fit the
+/// collection. Nothing is written when it is returned.
+#[derive(Debug)]
+pub struct EmbeddingError(pub anyhow::Error);
+
+impl fmt::Display for EmbeddingError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.0)
     }
 }
 
+impl std::error::Error for EmbeddingError {}
+
+
+/// Legacy id counter file. It is only read to migrate databases created before
+/// the counter moved into the collections registry.
+#[derive(Serialize, Deserialize, Default)]
+struct Config {
+    next_id: u32,
+}
+
 impl Config {
     fn load_config(path: &str) -> std::io::Result<Config> {
         let content = fs::read_to_string(path)?;
         let config: Config = serde_json::from_str(&content)?;
         Ok(config)
     }
+}
+
+/// One line of a JSONL export.
+#[derive(Deserialize, Serialize, Clone, Debug)]
+pub struct ExportRecord {
+    pub id: u32,
+    pub content: String,
+    #[serde(default)]
+    pub metadata: Value,
+    /// Only present when exported with embeddings; reused on import instead of re-embedding.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub embedding: Option<Vec<f32>>,
+}
+
+/// A named set of documents with its own arroy index, heed database and id counter.
+#[derive(Deserialize, Serialize, Clone, Debug)]
+pub struct Collection {
+    pub name: String,
+    pub index: u16,
+    pub next_id: u32,
+    pub dimensions: usize,
+    #[serde(default)]
+    pub metric: Metric,
+    /// Id of the embedding model the vectors were computed with, `None` for collections
+    /// created before it was recorded or without a named model.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub model: Option<String>,
+}
+
+pub fn is_valid_collection_name(name: &str) -> bool {
+    !name.is_empty()
+        && name.len() <= MAX_COLLECTION_NAME_LEN
+        && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+}
+
+/// Marks an id whose arroy item may not match its heed entry yet.
+///
+/// Every write first commits the heed entry, the collection counter and this record
+/// in a single heed transaction. The arroy side is applied afterwards and the record
+/// is removed once it is committed, so records left behind by a crash are replayed
+/// on the next open.
+#[derive(Deserialize, Serialize, Clone, Debug)]
+struct JournalRecord {
+    collection: String,
+    id: u32,
+}
+
+/// Marks the arroy index of a dropped collection that may still hold its items.
+///
+/// It is committed along with the removal of the collection from the registry, and
+/// removed once the index is cleared. Its index is not reused until then.
+#[derive(Deserialize, Serialize, Clone, Debug)]
+struct DropRecord {
+    index: u16,
+    dimensions: usize,
+    metric: Metric,
+}
+
+/// Refuses a collection whose vectors were computed by another model than the configured
+/// one. Collections created before the model id was recorded are only checked for their
+/// dimensions.
+fn check_model(collection: &Collection, model: &str, dimensions: usize) -> anyhow::Result<()> {
+    if let Some(recorded) = collection.model.as_deref()
+        && recorded != model
+    {
+        anyhow::bail!(
+            "Collection {} was built with model {}, but {} is configured",
+            collection.name, recorded, model
+        );
+    }
+    if collection.dimensions != dimensions {
+        anyhow::bail!(
+            "Collection {} has {} dimensions, but the model produces {}",
+            collection.name, collection.dimensions, dimensions
+        );
+    }
+    Ok(())
+}
+
+fn journal_key(collection: &str, id: u32) -> String {
+    format!("{}/{}", collection, id)
+}
+
+/// `#` is not allowed in collection names, so these never collide with write records.
+const DROP_KEY_PREFIX: &str = "#drop/";
+
+fn drop_key(index: u16) -> String {
+    format!("{}{}", DROP_KEY_PREFIX, index)
+}
+
+/// Drift found between the heed entries of a collection and its arroy items.
+#[derive(Serialize, Clone, Debug)]
+pub struct ConsistencyReport {
+    pub collection: String,
+    /// Entries stored in heed without a vector in arroy.
+    pub missing_vectors: Vec<u32>,
+    /// Vectors in arroy without an entry in heed.
+    pub orphan_vectors: Vec<u32>,
+    /// The id counter is not above every stored id, so new documents would reuse ids.
+    pub stale_next_id: bool,
+    /// The corpus tree does not match the stored embeddings, e.g. for collections
+    /// created before corpus roots were maintained.
+    pub stale_corpus_root: bool,
+    pub repaired: bool,
+}
+
+impl ConsistencyReport {
+    pub fn is_consistent(&self) -> bool {
+        self.missing_vectors.is_empty() && self.orphan_vectors.is_empty() && !self.stale_next_id && !self.stale_corpus_root
+    }
+}
 
-    fn save_config(path: &str, config: &Config) -> std::io::Result<()> {
-        let content = serde_json::to_string_pretty(config)?;
-        fs::write(path, content)
+fn documents_db_name(collection: &str) -> String {
+    if collection == DEFAULT_COLLECTION {
+        DEFAULT_DOCUMENTS_DB.to_string()
+    } else {
+        format!("documents-{}", collection)
     }
 }
 
-pub struct SimpleDBNN<T: Embeddable, D: Distance> {
+/// `heed_db`, `corpus_tree`, `index`, `next_id`, `dimensions` and `metric` always describe
+/// the active collection, selected with [`SimpleDBNN::use_collection`].
+pub struct SimpleDBNN<T: Embeddable> {
     pub env_db: Env,
     pub env_embedded: Env,
-    pub nn_db: ArroyDatabase<D>,
+    /// Shared by every collection, typed per metric with [`SimpleDBNN::nn_database`].
+    pub nn_db: HeedDatabase<Unspecified, Unspecified>,
     pub heed_db: HeedDatabase<BEU32, Bytes>,
+    pub corpus_tree: CorpusTree,
+    pub collections_db: HeedDatabase<Str, Bytes>,
+    pub journal_db: HeedDatabase<Str, Bytes>,
+    pub collection: String,
     pub next_id: u32,
+    #[cfg(test)]
     pub path_db: PathBuf,
+    #[cfg(test)]
     pub path_embedded: PathBuf,
+    #[cfg(test)]
     pub path_config: PathBuf,
     pub embed_engine: T,
     pub dimensions: usize,
+    pub metric: Metric,
     pub index: u16,
     pub rng: StdRng,
+    /// Items added or removed per arroy index since its tree was last built.
+    pub pending: HashMap<u16, usize>,
+    pub rebuild_threshold: usize,
+    pub rebuild_interval: Option<Duration>,
+    /// When the tree of each arroy index was last built, or got its first buffered write.
+    pub last_build: HashMap<u16, Instant>,
 }
 
 #[derive(Deserialize, Serialize, Clone, Debug)]
 pub struct DBEntry {
     pub content: String,
     pub embedding: Vec<f32>,
+    #[serde(default)]
+    pub metadata: Value,
+}
+
+impl DBEntry {
+    /// Every key of `filter` must be present in the metadata with an equal value.
+    /// Array values in the metadata match if they contain the filter value.
+    pub fn matches(&self, filter: &Map<String, Value>) -> bool {
+        filter.iter().all(|(key, expected)| match self.metadata.get(key) {
+            Some(Value::Array(values)) if !expected.is_array() => values.contains(expected),
+            Some(value) => value == expected,
+            None => false,
+        })
+    }
+
+    /// Leaf of the document `id` in the corpus tree of its collection.
+    pub fn leaf_hash(&self, id: u32) -> Hash {
+        leaf_hash(id, &document_digest(id, &self.content), &self.embedding)
+    }
 }
 
 
@@ -80,9 +304,16 @@ pub struct DBConfig<T> {
     pub embedded_path: PathBuf,
     pub config_path: PathBuf,
     pub embed_engine: T,
+    /// Must be the dimensions of `embed_engine`.
     pub dimensions: usize,
+    /// Metric of the default collection when the database is created.
+    pub metric: Metric,
     pub index: u16,
     pub seed: u64,
+    /// Number of buffered writes that triggers a rebuild of the arroy tree.
+    pub rebuild_threshold: usize,
+    /// Buffered writes older than this are built on the next write or flush.
+    pub rebuild_interval: Option<Duration>,
 }
 
 impl<T: Default> Default for DBConfig<T> {
@@ -93,47 +324,36 @@ impl<T: Default> Default for DBConfig<T> {
             config_path: PathBuf::from("config"),
             embed_engine: T::default(),
             dimensions: DEFAULT_DIMS,
-            index: 0,
-            seed: 42,
+            metric: Metric::default(),
+            index: INDEX_DEFAULT_NN,
+            seed: DEFAULT_SEED,
+            rebuild_threshold: DEFAULT_REBUILD_THRESHOLD,
+            rebuild_interval: Some(DEFAULT_REBUILD_INTERVAL),
         }
     }
 }
 
-impl<T: Default> DBConfig<T> {
-    pub fn from_base_dir<P: AsRef<Path>>(base_dir: P) -> Self {
+impl<T> DBConfig<T> {
+    pub fn with_engine<P: AsRef<Path>>(base_dir: P, embed_engine: T) -> Self {
         let base = base_dir.as_ref();
         DBConfig {
             db_path: base.join("db"),
             embedded_path: base.join("embedded"),
             config_path: base.join("config"),
-            embed_engine: T::default(),
-            dimensions: DEFAULT_DIMS,
-            index: 0,
-            seed: 42,
-        }
-    }
-}
-
-
-impl<T: Default> DBConfig<T> {
-    pub fn from_tempdir() -> Self {
-        let tempdir = tempfile::tempdir().expect("failed to create tempdir");
-        let base = tempdir.path();
-
-        DBConfig {
-            db_path: base.join("db"),
-            embedded_path: base.join("embedded"),
-            config_path: base.join("config"),
-            embed_engine: T::default(),
+            embed_engine,
             dimensions: DEFAULT_DIMS,
+            metric: Metric::default(),
             index: INDEX_DEFAULT_NN,
             seed: DEFAULT_SEED,
+            rebuild_threshold: DEFAULT_REBUILD_THRESHOLD,
+            rebuild_interval: Some(DEFAULT_REBUILD_INTERVAL),
         }
     }
 }
 
 
-impl<T: Embeddable, D: Distance> SimpleDBNN<T, D> {
+impl<T: Embeddable> SimpleDBNN<T> {
+    #[cfg(test)]
     pub fn new(
         db_path: PathBuf,
         embedded_path: PathBuf,
@@ -143,51 +363,17 @@ impl<T: Embeddable, D: Distance> SimpleDBNN<T, D> {
         index: u16,
         seed: u64,
     ) -> anyhow::Result<Self> {
-        let _ = rayon::ThreadPoolBuilder::new()
-            .num_threads(NUM_THREADS)
-            .build_global();
-        let _ = fs::create_dir_all(embedded_path.clone());
-        let embedded = unsafe {
-            EnvOpenOptions::new()
-                .map_size(MAP_SIZE) // 2GiB
-                .max_dbs(MAX_DBS)
-                .open(embedded_path.clone())
-        }?;
-        let _ = fs::create_dir_all(db_path.clone());
-        let db = unsafe {
-            EnvOpenOptions::new()
-                .map_size(MAP_SIZE) // 2GiB
-                .max_dbs(MAX_DBS)
-                .open(db_path.clone())
-        }?;
-
-        /* set up database for embedded */
-        let mut embedded_wtxn = embedded.write_txn()?;
-        let nn_db: ArroyDatabase<D> = embedded.create_database(&mut embedded_wtxn, None)?;
-        embedded_wtxn.commit()?;
-
-        /* heed db */
-        let mut db_rw_txn = db.write_txn()?;
-        let heed_db: HeedDatabase<BEU32, Bytes> =
-            db.create_database(&mut db_rw_txn, Some("serde-bincode"))?;
-        db_rw_txn.commit()?;
-
-        let config = Config::load_config(config_path.to_str().expect("Could not load config path"))
-            .unwrap_or_default();
-        let rng = StdRng::seed_from_u64(seed);
-        Ok(SimpleDBNN {
-            nn_db,
-            heed_db,
-            env_db: db,
-            env_embedded: embedded,
-            next_id: config.next_id,
-            path_db: db_path,
-            path_embedded: embedded_path,
-            path_config: config_path,
+        Self::from_config(DBConfig {
+            db_path,
+            embedded_path,
+            config_path,
             embed_engine,
             dimensions,
+            metric: Metric::default(),
             index,
-            rng,
+            seed,
+            rebuild_threshold: DEFAULT_REBUILD_THRESHOLD,
+            rebuild_interval: Some(DEFAULT_REBUILD_INTERVAL),
         })
     }
 
@@ -213,70 +399,334 @@ impl<T: Embeddable, D: Distance> SimpleDBNN<T, D> {
         }?;
 
         let mut embedded_wtxn = embedded.write_txn()?;
-        let nn_db: ArroyDatabase<D> = embedded.create_database(&mut embedded_wtxn, None)?;
+        let nn_db: HeedDatabase<Unspecified, Unspecified> = embedded.create_database(&mut embedded_wtxn, None)?;
         embedded_wtxn.commit()?;
 
+        let loaded_config = Config::load_config(config.config_path.to_str().unwrap())
+            .unwrap_or_default();
+
         let mut db_rw_txn = db.write_txn()?;
         let heed_db: HeedDatabase<BEU32, Bytes> =
-            db.create_database(&mut db_rw_txn, Some("serde-bincode"))?;
+            db.create_database(&mut db_rw_txn, Some(DEFAULT_DOCUMENTS_DB))?;
+        let collections_db: HeedDatabase<Str, Bytes> =
+            db.create_database(&mut db_rw_txn, Some(COLLECTIONS_DB))?;
+        let journal_db: HeedDatabase<Str, Bytes> =
+            db.create_database(&mut db_rw_txn, Some(JOURNAL_DB))?;
+        let model = config.embed_engine.model_id().to_string();
+        if config.dimensions != config.embed_engine.dimensions() {
+            anyhow::bail!(
+                "Model {} produces {} dimensions, but {} are configured",
+                model, config.embed_engine.dimensions(), config.dimensions
+            );
+        }
+        for elem in collections_db.iter(&db_rw_txn)? {
+            let (_, bytes) = elem?;
+            check_model(&serde_json::from_slice(bytes)?, &model, config.dimensions)?;
+        }
+        /* databases created before collections existed only have the config file */
+        let mut default_collection = match collections_db.get(&db_rw_txn, DEFAULT_COLLECTION)? {
+            Some(bytes) => serde_json::from_slice::<Collection>(bytes)?,
+            None => Collection {
+                name: DEFAULT_COLLECTION.to_string(),
+                index: config.index,
+                next_id: 0,
+                dimensions: config.dimensions,
+                metric: config.metric,
+                model: Some(model.clone()),
+            },
+        };
+        default_collection.next_id = default_collection.next_id.max(loaded_config.next_id);
+        if default_collection.model.is_none() {
+            default_collection.model = Some(model);
+        }
+        let corpus_tree = CorpusTree::open(&db, &mut db_rw_txn, DEFAULT_COLLECTION)?;
+        collections_db.put(&mut db_rw_txn, DEFAULT_COLLECTION, &serde_json::to_vec(&default_collection)?)?;
         db_rw_txn.commit()?;
 
-        let loaded_config = Config::load_config(config.config_path.to_str().unwrap())
-            .unwrap_or_default();
         let rng = StdRng::seed_from_u64(config.seed);
 
-        Ok(SimpleDBNN {
+        let mut simple_db = SimpleDBNN {
             nn_db,
             heed_db,
+            corpus_tree,
+            collections_db,
+            journal_db,
+            collection: default_collection.name,
             env_db: db,
             env_embedded: embedded,
-            next_id: loaded_config.next_id,
+            next_id: default_collection.next_id,
+            #[cfg(test)]
             path_db: config.db_path,
+            #[cfg(test)]
             path_embedded: config.embedded_path,
+            #[cfg(test)]
             path_config: config.config_path,
             embed_engine: config.embed_engine,
-            dimensions: config.dimensions,
-            index: config.index,
+            dimensions: default_collection.dimensions,
+            metric: default_collection.metric,
+            index: default_collection.index,
             rng,
+            pending: HashMap::new(),
+            rebuild_threshold: config.rebuild_threshold,
+            rebuild_interval: config.rebuild_interval,
+            last_build: HashMap::new(),
+        };
+        simple_db.replay_journal()?;
+        Ok(simple_db)
+    }
+
+    fn get_collection(&self, name: &str) -> anyhow::Result<Option<Collection>> {
+        let rotxn = self.env_db.read_txn()?;
+        let Some(bytes) = self.collections_db.get(&rotxn, name)? else {
+            return Ok(None);
+        };
+        Ok(Some(serde_json::from_slice(bytes)?))
+    }
+
+    pub fn list_collections(&self) -> anyhow::Result<Vec<Collection>> {
+        let rotxn = self.env_db.read_txn()?;
+        let mut collections = Vec::new();
+        for elem in self.collections_db.iter(&rotxn)? {
+            let (_, bytes) = elem?;
+            collections.push(serde_json::from_slice(bytes)?);
+        }
+        Ok(collections)
+    }
+
+    /// Registers a new collection on the first free arroy index.
+    /// Returns `None` if a collection with that name already exists.
+    pub fn create_collection(&mut self, name: &str, dimensions: usize, metric: Metric) -> anyhow::Result<Option<Collection>> {
+        if !is_valid_collection_name(name) {
+            anyhow::bail!("Invalid collection name: {:?}", name);
+        }
+        let mut txn = self.env_db.write_txn()?;
+        if self.collections_db.get(&txn, name)?.is_some() {
+            return Ok(None);
+        }
+        let mut used_indexes = Vec::new();
+        for elem in self.collections_db.iter(&txn)? {
+            let (_, bytes) = elem?;
+            used_indexes.push(serde_json::from_slice::<Collection>(bytes)?.index);
+        }
+        if used_indexes.len() >= MAX_COLLECTIONS {
+            anyhow::bail!("At most {} collections can exist at once", MAX_COLLECTIONS);
+        }
+        let mut index = None;
+        for candidate in 0..=u16::MAX {
+            if !used_indexes.contains(&candidate) && self.journal_db.get(&txn, &drop_key(candidate))?.is_none() {
+                index = Some(candidate);
+                break;
+            }
+        }
+        let index = index.ok_or_else(|| anyhow::anyhow!("No free index left for a new collection"))?;
+
+        let _: HeedDatabase<BEU32, Bytes> =
+            self.env_db.create_database(&mut txn, Some(&documents_db_name(name)))?;
+        CorpusTree::open(&self.env_db, &mut txn, name)?;
+        let collection = Collection {
+            name: name.to_string(),
+            index,
+            next_id: 0,
+            dimensions,
+            metric,
+            model: Some(self.embed_engine.model_id().to_string()),
+        };
+        self.collections_db.put(&mut txn, name, &serde_json::to_vec(&collection)?)?;
+        txn.commit()?;
+        Ok(Some(collection))
+    }
+
+    /// Removes every document of the collection, deletes its databases and unregisters it.
+    /// Returns `false` if the collection does not exist.
+    pub fn drop_collection(&mut self, name: &str) -> anyhow::Result<bool> {
+        if name == DEFAULT_COLLECTION {
+            anyhow::bail!("The default collection can not be dropped");
+        }
+        let Some(collection) = self.get_collection(name)? else {
+            return Ok(false);
+        };
+        // heed is unregistered first, a crash before arroy is cleared is replayed on open
+        let dropped = self.unregister_collection(collection)?;
+        self.clear_dropped(&dropped)?;
+        Ok(true)
+    }
+
+    /// Deletes the databases and the registry entry of the collection, journaling its index.
+    fn unregister_collection(&mut self, collection: Collection) -> anyhow::Result<DropRecord> {
+        let name = collection.name.as_str();
+        // no handle on the databases about to be deleted may stay around
+        if self.collection == name {
+            self.use_collection(DEFAULT_COLLECTION)?;
+        }
+        let dropped = DropRecord { index: collection.index, dimensions: collection.dimensions, metric: collection.metric };
+        let mut txn = self.env_db.write_txn()?;
+        let documents: HeedDatabase<BEU32, Bytes> =
+            self.env_db.create_database(&mut txn, Some(&documents_db_name(name)))?;
+        // SAFETY: the handles are local, the active collection was switched away above
+        unsafe { documents.remove(&mut txn)? };
+        CorpusTree::open(&self.env_db, &mut txn, name)?.remove(&mut txn)?;
+        self.collections_db.delete(&mut txn, name)?;
+        self.journal_db.put(&mut txn, &drop_key(dropped.index), &serde_json::to_vec(&dropped)?)?;
+        txn.commit()?;
+        Ok(dropped)
+    }
+
+    /// Clears the arroy index of a dropped collection and frees the index for reuse.
+    fn clear_dropped(&mut self, dropped: &DropRecord) -> anyhow::Result<()> {
+        let env = self.env_embedded.clone();
+        let mut wtxn = env.write_txn()?;
+        with_distance!(dropped.metric, D => {
+            self.nn_writer::<D>(dropped.index, dropped.dimensions).clear(&mut wtxn)
+        })?;
+        wtxn.commit()?;
+        self.pending.remove(&dropped.index);
+        self.last_build.remove(&dropped.index);
+
+        let mut txn = self.env_db.write_txn()?;
+        self.journal_db.delete(&mut txn, &drop_key(dropped.index))?;
+        txn.commit()?;
+        Ok(())
+    }
+
+    /// Makes `name` the collection used by `put`, `get`, `delete` and `update`.
+    /// Returns `false` if the collection does not exist.
+    pub fn use_collection(&mut self, name: &str) -> anyhow::Result<bool> {
+        if self.collection == name {
+            return Ok(true);
+        }
+        let Some(collection) = self.get_collection(name)? else {
+            return Ok(false);
+        };
+        self.heed_db = self.documents_db(name)?;
+        self.corpus_tree = self.open_corpus_tree(name)?;
+        self.collection = collection.name;
+        self.index = collection.index;
+        self.next_id = collection.next_id;
+        self.dimensions = collection.dimensions;
+        self.metric = collection.metric;
+        Ok(true)
+    }
+
+    fn documents_db(&self, collection: &str) -> anyhow::Result<HeedDatabase<BEU32, Bytes>> {
+        let rotxn = self.env_db.read_txn()?;
+        let documents = self
+            .env_db
+            .open_database(&rotxn, Some(&documents_db_name(collection)))?
+            .ok_or_else(|| anyhow::anyhow!("Missing documents database for collection {}", collection))?;
+        rotxn.commit()?;
+        Ok(documents)
+    }
+
+    fn open_corpus_tree(&self, collection: &str) -> anyhow::Result<CorpusTree> {
+        let mut txn = self.env_db.write_txn()?;
+        let tree = CorpusTree::open(&self.env_db, &mut txn, collection)?;
+        txn.commit()?;
+        Ok(tree)
+    }
+
+    /// Root of the active collection and the version of the tree it was taken at.
+    pub fn corpus_root(&self) -> anyhow::Result<CorpusRoot> {
+        let rotxn = self.env_db.read_txn()?;
+        Ok(CorpusRoot {
+            collection: self.collection.clone(),
+            root: hex::encode(self.corpus_tree.root(&rotxn)?),
+            version: self.corpus_tree.version(&rotxn)?,
+            documents: self.heed_db.len(&rotxn)?,
         })
     }
 
-    pub fn get_current_id(self) -> u32 {
-        self.next_id
+    /// Proof that the stored embedding of `id` is in the active collection.
+    /// Returns `None` if the id is unknown.
+    pub fn inclusion_proof(&self, id: u32) -> anyhow::Result<Option<InclusionProof>> {
+        let rotxn = self.env_db.read_txn()?;
+        if self.heed_db.get(&rotxn, &id)?.is_none() {
+            return Ok(None);
+        }
+        Ok(Some(InclusionProof {
+            root: hex::encode(self.corpus_tree.root(&rotxn)?),
+            version: self.corpus_tree.version(&rotxn)?,
+            path: self.corpus_tree.inclusion_path(&rotxn, id)?.iter().map(hex::encode).collect(),
+        }))
     }
-    pub fn update_id(&mut self, id: u32) {
-        self.next_id = id;
+
+    pub fn nn_database<D: Distance>(&self) -> ArroyDatabase<D> {
+        self.nn_db.remap_types()
     }
 
-    pub fn nn_writer(&mut self, index: u16, dimensions: usize) -> Writer<D> {
-        Writer::<D>::new(self.nn_db, index, dimensions)
+    pub fn nn_writer<D: Distance>(&self, index: u16, dimensions: usize) -> Writer<D> {
+        Writer::<D>::new(self.nn_database(), index, dimensions)
     }
 
 
 
-    fn put_db(&mut self, content: &str, id: u32, embedding: Vec<f32> ) -> anyhow::Result<()> {
+    /// Journals `ids` and stores the collection counter past them, as part of the
+    /// same transaction that writes their entries.
+    fn journal(&self, txn: &mut RwTxn, ids: &[u32]) -> anyhow::Result<u32> {
+        for &id in ids {
+            let record = JournalRecord { collection: self.collection.clone(), id };
+            self.journal_db.put(txn, &journal_key(&record.collection, id), &serde_json::to_vec(&record)?)?;
+        }
+        let next_id = ids.iter().map(|id| id + 1).fold(self.next_id, u32::max);
+        // the registry entry also holds the model id, which is not mirrored on `self`
+        let model = match self.collections_db.get(txn, &self.collection)? {
+            Some(bytes) => serde_json::from_slice::<Collection>(bytes)?.model,
+            None => None,
+        };
+        let collection = Collection {
+            name: self.collection.clone(),
+            index: self.index,
+            next_id,
+            dimensions: self.dimensions,
+            metric: self.metric,
+            model,
+        };
+        self.collections_db.put(txn, &collection.name, &serde_json::to_vec(&collection)?)?;
+        Ok(next_id)
+    }
+
+    fn clear_journal(&self, ids: &[u32]) -> anyhow::Result<()> {
+        let mut txn = self.env_db.write_txn()?;
+        for &id in ids {
+            self.journal_db.delete(&mut txn, &journal_key(&self.collection, id))?;
+        }
+        txn.commit()?;
+        Ok(())
+    }
+
+    fn put_db(&mut self, content: &str, id: u32, embedding: Vec<f32>, metadata: Value) -> anyhow::Result<()> {
         let mut txn = self.env_db.write_txn()?;
         let entry = DBEntry {
             content: String::from(content),
-            embedding};
+            embedding,
+            metadata};
 
         let bytes = serde_json::to_vec(&entry)?;
         self.heed_db.put(&mut txn, &id, &bytes)?;
+        self.corpus_tree.set_leaves(&mut txn, &[(id, Some(entry.leaf_hash(id)))])?;
+        let next_id = self.journal(&mut txn, &[id])?;
         txn.commit()?;
+        self.next_id = next_id;
         Ok(())
     }
 
-    fn put_batch_db(&mut self, batch: &Vec<(&str, u32, Vec<f32>)>) -> anyhow::Result<()> {
+    fn put_batch_db(&mut self, batch: &[(u32, DBEntry)]) -> anyhow::Result<()> {
         let mut txn = self.env_db.write_txn()?;
-        for (content, id, embedding) in batch {
-            let db_entry = DBEntry{content: content.to_string(), embedding: embedding.to_vec()};
-            self.heed_db.put(&mut txn, &id, serde_json::to_vec(&db_entry)?.as_ref())?;
+        for (id, db_entry) in batch {
+            self.heed_db.put(&mut txn, id, serde_json::to_vec(db_entry)?.as_ref())?;
         }
+        let leaves = batch
+            .iter()
+            .map(|(id, db_entry)| (*id, Some(db_entry.leaf_hash(*id))))
+            .collect::<Vec<(u32, Option<Hash>)>>();
+        self.corpus_tree.set_leaves(&mut txn, &leaves)?;
+        let ids = batch.iter().map(|(id, _)| *id).collect::<Vec<u32>>();
+        let next_id = self.journal(&mut txn, &ids)?;
         txn.commit()?;
+        self.next_id = next_id;
         Ok(())
     }
 
-    fn get_db(&mut self, id: u32) -> anyhow::Result<Option<DBEntry>> {
+    pub fn get_db(&mut self, id: u32) -> anyhow::Result<Option<DBEntry>> {
         let rotxn = self.env_db.read_txn()?;
         let Ok(elem) = self.heed_db.get(&rotxn, &id) else {
             return Ok(None);
@@ -285,167 +735,678 @@ impl<T: Embeddable, D: Distance> SimpleDBNN<T, D> {
             return Ok(None);
         };
 
-        let s = String::from_utf8_lossy(bytes);
         let entry: DBEntry = serde_json::from_slice(bytes)?;
         Ok(Some(entry))
     }
 
+    /// Embeds `content` and checks that the vector fits the active collection.
+    fn embed(&self, content: &str) -> anyhow::Result<Vec<f32>> {
+        let embedding = self.embed_engine.to_embedding(content.as_bytes()).map_err(EmbeddingError)?;
+        self.check_embedding(&embedding)?;
+        Ok(embedding)
+    }
+
+    /// Embeds a search query, see [`Embeddable::to_query_embedding`].
+    #[cfg(test)]
+    fn embed_query(&self, query: &str) -> anyhow::Result<Vec<f32>> {
+        let embedding = self.embed_engine.to_query_embedding(query.as_bytes()).map_err(EmbeddingError)?;
+        self.check_embedding(&embedding)?;
+        Ok(embedding)
+    }
+
+    /// [`SimpleDBNN::embed`] for many contents, in one call of the engine.
+    fn embed_all(&self, contents: &[&[u8]]) -> anyhow::Result<Vec<Vec<f32>>> {
+        let embeddings = self.embed_engine.to_embeddings(contents).map_err(EmbeddingError)?;
+        if embeddings.len() != contents.len() {
+            return Err(EmbeddingError(anyhow::anyhow!(
+                "Got {} embeddings for {} documents", embeddings.len(), contents.len()
+            )).into());
+        }
+        for embedding in &embeddings {
+            self.check_embedding(embedding)?;
+        }
+        Ok(embeddings)
+    }
+
+    fn check_embedding(&self, embedding: &[f32]) -> anyhow::Result<()> {
+        if embedding.len() != self.dimensions {
+            return Err(EmbeddingError(anyhow::anyhow!(
+                "Embedding has {} dimensions, collection {} expects {}",
+                embedding.len(), self.collection, self.dimensions
+            )).into());
+        }
+        if !embedding.iter().all(|value| value.is_finite()) {
+            return Err(EmbeddingError(anyhow::anyhow!("Embedding has non finite components")).into());
+        }
+        Ok(())
+    }
+
+    #[cfg(test)]
     fn put_nn(&mut self, content: &str, id: u32, index: u16) -> anyhow::Result<Vec<f32>> {
-        let embedding = self.embed_engine.to_embedding(content.as_bytes().to_vec());
+        let embedding = self.embed(content)?;
+        self.add_nn(id, index, &embedding)?;
+        Ok(embedding)
+    }
+
+    fn add_nn(&mut self, id: u32, index: u16, embedding: &[f32]) -> anyhow::Result<()> {
         let env = self.env_embedded.clone();
         let mut wtxn = env.write_txn()?;
-        let writer = self.nn_writer(index, self.dimensions);
-        writer.add_item(&mut wtxn, id, embedding.clone().as_slice())?;
-        writer.builder(&mut self.rng).build(&mut wtxn)?;
+        with_distance!(self.metric, D => {
+            self.nn_writer::<D>(index, self.dimensions).add_item(&mut wtxn, id, embedding)
+        })?;
         wtxn.commit()?;
-        Ok(embedding)
+        self.mark_pending(index, 1)
+    }
+
+    fn put_batch_nn(&mut self, batch: &[(u32, DBEntry)], index: u16) -> anyhow::Result<()> {
+        let env = self.env_embedded.clone();
+        let mut wtxn = env.write_txn()?;
+        with_distance!(self.metric, D => {
+            let writer = self.nn_writer::<D>(index, self.dimensions);
+            for (id, entry) in batch {
+                writer.add_item(&mut wtxn, *id, entry.embedding.as_slice())?;
+            }
+        });
+        wtxn.commit()?;
+        self.mark_pending(index, batch.len())
+    }
+
+
+    fn delete_db(&mut self, id: u32) -> anyhow::Result<bool> {
+        let mut txn = self.env_db.write_txn()?;
+        let deleted = self.heed_db.delete(&mut txn, &id)?;
+        if deleted {
+            self.corpus_tree.set_leaves(&mut txn, &[(id, None)])?;
+        }
+        self.journal(&mut txn, &[id])?;
+        txn.commit()?;
+        Ok(deleted)
+    }
+
+    fn delete_nn(&mut self, id: u32, index: u16) -> anyhow::Result<bool> {
+        let env = self.env_embedded.clone();
+        let mut wtxn = env.write_txn()?;
+        let deleted = with_distance!(self.metric, D => {
+            self.nn_writer::<D>(index, self.dimensions).del_item(&mut wtxn, id)
+        })?;
+        wtxn.commit()?;
+        if deleted {
+            self.mark_pending(index, 1)?;
+        }
+        Ok(deleted)
+    }
+
+    fn build_nn(&mut self, index: u16, dimensions: usize, metric: Metric) -> anyhow::Result<()> {
+        let env = self.env_embedded.clone();
+        let mut wtxn = env.write_txn()?;
+        with_distance!(metric, D => {
+            self.nn_writer::<D>(index, dimensions).builder(&mut self.rng).build(&mut wtxn)
+        })?;
+        wtxn.commit()?;
+        self.pending.remove(&index);
+        self.last_build.insert(index, Instant::now());
+        Ok(())
+    }
+
+    /// Buffers writes to an index and only rebuilds its tree once the threshold
+    /// or the rebuild interval is reached.
+    fn mark_pending(&mut self, index: u16, count: usize) -> anyhow::Result<()> {
+        let pending = self.pending.entry(index).or_default();
+        *pending += count;
+        let last_build = *self.last_build.entry(index).or_insert_with(Instant::now);
+        let interval_elapsed = self
+            .rebuild_interval
+            .is_some_and(|interval| last_build.elapsed() >= interval);
+        if *pending >= self.rebuild_threshold || interval_elapsed {
+            self.build_nn(index, self.dimensions, self.metric)?;
+        }
+        Ok(())
     }
 
-    fn put_batch_nn(&mut self, batch: &Vec<(&str, u32)>, index: u16) -> anyhow::Result<Vec<Vec<f32>>> {
+    /// Builds the tree before reading if it has buffered writes, including
+    /// those left unbuilt by a previous process.
+    fn ensure_built(&mut self, index: u16, dimensions: usize, metric: Metric) -> anyhow::Result<()> {
+        let env = self.env_embedded.clone();
+        let rtxn = env.read_txn()?;
+        let need_build = self.pending.contains_key(&index)
+            || with_distance!(metric, D => self.nn_writer::<D>(index, dimensions).need_build(&rtxn))?;
+        drop(rtxn);
+        if need_build {
+            self.build_nn(index, dimensions, metric)?;
+        }
+        Ok(())
+    }
+
+    /// Makes the arroy item of `id` match its heed entry: the stored embedding is
+    /// re-added, or the item is removed if the entry no longer exists.
+    fn sync_nn(&mut self, collection: &Collection, documents: HeedDatabase<BEU32, Bytes>, id: u32) -> anyhow::Result<()> {
+        let rotxn = self.env_db.read_txn()?;
+        let entry = match documents.get(&rotxn, &id)? {
+            Some(bytes) => Some(serde_json::from_slice::<DBEntry>(bytes)?),
+            None => None,
+        };
+        drop(rotxn);
+
         let env = self.env_embedded.clone();
         let mut wtxn = env.write_txn()?;
-        let writer = self.nn_writer(index, self.dimensions);
-        let mut embeds: Vec<Vec<f32>> = Vec::new();
-        for (content, id) in batch {
-            let embedding = self.embed_engine.to_embedding(content.as_bytes().to_vec());
-            writer.add_item(&mut wtxn, *id, embedding.as_slice())?;
-            embeds.push(embedding);
-        }
-        writer.builder(&mut self.rng).build(&mut wtxn)?;
+        with_distance!(collection.metric, D => {
+            let writer = self.nn_writer::<D>(collection.index, collection.dimensions);
+            match entry {
+                Some(entry) => writer.add_item(&mut wtxn, id, &entry.embedding)?,
+                None => {
+                    writer.del_item(&mut wtxn, id)?;
+                }
+            }
+        });
         wtxn.commit()?;
-        Ok(embeds)
+        *self.pending.entry(collection.index).or_default() += 1;
+        Ok(())
+    }
+
+    /// Applies the arroy side of writes interrupted by a crash.
+    fn replay_journal(&mut self) -> anyhow::Result<usize> {
+        let rotxn = self.env_db.read_txn()?;
+        let mut records = Vec::new();
+        let mut dropped = Vec::new();
+        for elem in self.journal_db.iter(&rotxn)? {
+            let (key, bytes) = elem?;
+            if key.starts_with(DROP_KEY_PREFIX) {
+                dropped.push(serde_json::from_slice::<DropRecord>(bytes)?);
+            } else {
+                records.push(serde_json::from_slice::<JournalRecord>(bytes)?);
+            }
+        }
+        drop(rotxn);
+
+        for dropped in &dropped {
+            self.clear_dropped(dropped)?;
+        }
+        for record in &records {
+            // writes to a dropped collection went away with its index
+            if let Some(collection) = self.get_collection(&record.collection)? {
+                let documents = self.documents_db(&collection.name)?;
+                self.sync_nn(&collection, documents, record.id)?;
+            }
+        }
+
+        let mut txn = self.env_db.write_txn()?;
+        self.journal_db.clear(&mut txn)?;
+        txn.commit()?;
+        Ok(records.len() + dropped.len())
+    }
+
+    /// Compares the heed entries and arroy items of every collection, and the id
+    /// counters against the stored ids. With `repair`, heed is taken as the source
+    /// of truth and arroy and the counters are brought back in line with it.
+    pub fn check_consistency(&mut self, repair: bool) -> anyhow::Result<Vec<ConsistencyReport>> {
+        let mut reports = Vec::new();
+        for collection in self.list_collections()? {
+            reports.push(self.check_collection(collection, repair)?);
+        }
+        Ok(reports)
+    }
+
+    fn check_collection(&mut self, collection: Collection, repair: bool) -> anyhow::Result<ConsistencyReport> {
+        let documents = self.documents_db(&collection.name)?;
+        let corpus_tree = self.open_corpus_tree(&collection.name)?;
+        let rotxn = self.env_db.read_txn()?;
+        let mut stored = RoaringBitmap::new();
+        let mut leaves = Vec::new();
+        for elem in documents.iter(&rotxn)? {
+            let (id, bytes) = elem?;
+            let entry: DBEntry = serde_json::from_slice(bytes)?;
+            stored.insert(id);
+            leaves.push((id, entry.leaf_hash(id)));
+        }
+        let stale_corpus_root = corpus_tree.root(&rotxn)? != merkle_root(&leaves);
+        drop(rotxn);
+
+        self.ensure_built(collection.index, collection.dimensions, collection.metric)?;
+        let env = self.env_embedded.clone();
+        let rtxn = env.read_txn()?;
+        let indexed = with_distance!(collection.metric, D => {
+            Reader::<D>::open(&rtxn, collection.index, self.nn_database())?.item_ids().clone()
+        });
+        drop(rtxn);
+
+        let mut report = ConsistencyReport {
+            collection: collection.name.clone(),
+            missing_vectors: (&stored - &indexed).iter().collect(),
+            orphan_vectors: (&indexed - &stored).iter().collect(),
+            stale_next_id: stored.max().is_some_and(|max| max >= collection.next_id),
+            stale_corpus_root,
+            repaired: false,
+        };
+        if !repair || report.is_consistent() {
+            return Ok(report);
+        }
+
+        for &id in report.missing_vectors.iter().chain(report.orphan_vectors.iter()) {
+            self.sync_nn(&collection, documents, id)?;
+        }
+        if report.stale_next_id {
+            let next_id = stored.max().map_or(0, |max| max + 1);
+            let mut txn = self.env_db.write_txn()?;
+            let repaired = Collection { next_id, ..collection.clone() };
+            self.collections_db.put(&mut txn, &collection.name, &serde_json::to_vec(&repaired)?)?;
+            txn.commit()?;
+            if self.collection == collection.name {
+                self.next_id = next_id;
+            }
+        }
+        if report.stale_corpus_root {
+            let mut txn = self.env_db.write_txn()?;
+            corpus_tree.rebuild(&mut txn, &leaves)?;
+            txn.commit()?;
+        }
+        self.ensure_built(collection.index, collection.dimensions, collection.metric)?;
+        report.repaired = true;
+        Ok(report)
+    }
+
+    /// Builds every index that has buffered writes.
+    pub fn flush(&mut self) -> anyhow::Result<()> {
+        if self.pending.is_empty() {
+            return Ok(());
+        }
+        for collection in self.list_collections()? {
+            if self.pending.contains_key(&collection.index) {
+                self.build_nn(collection.index, collection.dimensions, collection.metric)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Rebuilds the tree of the active collection even if nothing is buffered.
+    pub fn rebuild(&mut self) -> anyhow::Result<()> {
+        self.build_nn(self.index, self.dimensions, self.metric)
     }
 
+    /// Ids of the stored entries accepted by `predicate`, used as arroy search candidates.
+    fn candidates<F: Fn(&DBEntry) -> bool>(&self, predicate: F) -> anyhow::Result<RoaringBitmap> {
+        let rotxn = self.env_db.read_txn()?;
+        let mut candidates = RoaringBitmap::new();
+        for elem in self.heed_db.iter(&rotxn)? {
+            let (id, bytes) = elem?;
+            let entry: DBEntry = serde_json::from_slice(bytes)?;
+            if predicate(&entry) {
+                candidates.insert(id);
+            }
+        }
+        Ok(candidates)
+    }
 
+    #[cfg(test)]
     fn get_nn(
         &mut self,
         content: &str,
         index: u16,
         n_results: usize,
     ) -> anyhow::Result<Vec<(u32, f32)>> {
-        let embedding = self.embed_engine.to_embedding(content.as_bytes().to_vec());
+        let embedding = self.embed_query(content)?;
+        self.get_nn_with_candidates(&embedding, index, n_results, None)
+    }
+
+    fn get_nn_with_candidates(
+        &mut self,
+        embedding: &[f32],
+        index: u16,
+        n_results: usize,
+        candidates: Option<&RoaringBitmap>,
+    ) -> anyhow::Result<Vec<(u32, f32)>> {
+        self.ensure_built(index, self.dimensions, self.metric)?;
         let rotxn = self.env_embedded.read_txn()?;
-        let reader = Reader::<D>::open(&rotxn, index, self.nn_db)?;
-        let query = reader.nns(n_results);
-        let results = query.by_vector(&rotxn, embedding.as_slice())?;
+        let results = with_distance!(self.metric, D => {
+            let reader = Reader::<D>::open(&rotxn, index, self.nn_database())?;
+            let mut query = reader.nns(n_results);
+            if let Some(candidates) = candidates {
+                query.candidates(candidates);
+            }
+            query.by_vector(&rotxn, embedding)?
+        });
         let ret_results = results
             .iter()
-            .map(|&(itemid, near)| (itemid as u32, near))
+            .map(|&(itemid, near)| (itemid, near))
             .collect::<Vec<(u32, f32)>>();
         Ok(ret_results)
     }
 
 
+    #[cfg(test)]
     pub fn put(&mut self, content: &str) -> anyhow::Result<Vec<f32>> {
+        self.put_with_metadata(content, Value::Null)
+    }
+
+    pub fn put_with_metadata(&mut self, content: &str, metadata: Value) -> anyhow::Result<Vec<f32>> {
         let current_id = self.next_id;
-        let embedding = self.put_nn(content, current_id, self.index)?;
-        self.put_db(content, current_id,  embedding.clone())?;
-        self.next_id = self.next_id + 1;
-        self.save_backup()?;
+        let embedding = self.embed(content)?;
+        self.put_db(content, current_id, embedding.clone(), metadata)?;
+        self.add_nn(current_id, self.index, &embedding)?;
+        self.clear_journal(&[current_id])?;
         Ok(embedding)
     }
 
-    fn save_backup(&mut self) -> anyhow::Result<()> {
-        Config::save_config(
-            self.path_config
-                .to_str()
-                .expect("Could not save config path"),
-            &Config {
-                next_id: self.next_id,
-            },
-        )?;
-        Ok(())
+    #[cfg(test)]
+    pub fn get(&mut self, content: &str, nn: usize) -> anyhow::Result<Vec<(u32, f32, DBEntry)>> {
+        let embedding = self.embed_query(content)?;
+        self.get_by_embedding(&embedding, nn)
     }
 
-    pub fn get(&mut self, content: &str, nn: usize) -> anyhow::Result<Vec<(u32, f32, DBEntry)>> {
-        let nears = self.get_nn(content, self.index, nn)?;
+    /// Nearest neighbours restricted to the entries accepted by `predicate`.
+    /// The filter is applied inside the arroy query, so `nn` results are returned
+    /// whenever enough entries match.
+    #[cfg(test)]
+    pub fn get_filtered<F: Fn(&DBEntry) -> bool>(
+        &mut self,
+        content: &str,
+        nn: usize,
+        predicate: F,
+    ) -> anyhow::Result<Vec<(u32, f32, DBEntry)>> {
+        let embedding = self.embed_query(content)?;
+        self.get_filtered_by_embedding(&embedding, nn, predicate)
+    }
 
-        let results = nears
-            .iter()
-            .map(|&(index, dist)| {
-                let val = self.get_db(index).unwrap().unwrap();
-                (index, dist, val)
-            })
-            .collect::<Vec<(u32, f32, DBEntry)>>();
+    /// [`SimpleDBNN::get`] for a query embedded by the caller, so the same vector can be
+    /// reused outside of the store, e.g. as a proof input.
+    pub fn get_by_embedding(&mut self, embedding: &[f32], nn: usize) -> anyhow::Result<Vec<(u32, f32, DBEntry)>> {
+        self.check_embedding(embedding)?;
+        self.get_with_candidates(embedding, nn, None)
+    }
+
+    pub fn get_filtered_by_embedding<F: Fn(&DBEntry) -> bool>(
+        &mut self,
+        embedding: &[f32],
+        nn: usize,
+        predicate: F,
+    ) -> anyhow::Result<Vec<(u32, f32, DBEntry)>> {
+        self.check_embedding(embedding)?;
+        let candidates = self.candidates(predicate)?;
+        self.get_with_candidates(embedding, nn, Some(&candidates))
+    }
+
+    fn get_with_candidates(
+        &mut self,
+        embedding: &[f32],
+        nn: usize,
+        candidates: Option<&RoaringBitmap>,
+    ) -> anyhow::Result<Vec<(u32, f32, DBEntry)>> {
+        let nears = self.get_nn_with_candidates(embedding, self.index, nn, candidates)?;
+
+        let mut results = Vec::with_capacity(nears.len());
+        for (index, dist) in nears {
+            match self.get_db(index)? {
+                Some(val) => results.push((index, dist, val)),
+                // heed is the source of truth, the next consistency check drops the stale item
+                None => tracing::warn!("Item {} of collection {} has no document", index, self.collection),
+            }
+        }
         Ok(results)
     }
 
-    pub fn put_batch(&mut self, batch: Vec<&str>, index: u16) -> anyhow::Result<()> {
-        let mut good_id_to_assign = self.next_id;
-        let batch_with_indexes = batch
+    fn write_batch(&mut self, batch: &[(u32, DBEntry)]) -> anyhow::Result<()> {
+        self.put_batch_db(batch)?;
+        self.put_batch_nn(batch, self.index)?;
+        let ids = batch.iter().map(|(id, _)| *id).collect::<Vec<u32>>();
+        self.clear_journal(&ids)
+    }
+
+    /// Every `(id, document_digest(id, content), embedding)` of the active collection, in increasing id order.
+    pub fn corpus(&self) -> anyhow::Result<Vec<(u32, Digest, Vec<f32>)>> {
+        let rotxn = self.env_db.read_txn()?;
+        let mut corpus = Vec::new();
+        for elem in self.heed_db.iter(&rotxn)? {
+            let (id, bytes) = elem?;
+            let entry: DBEntry = serde_json::from_slice(bytes)?;
+            corpus.push((id, document_digest(id, &entry.content), entry.embedding));
+        }
+        Ok(corpus)
+    }
+
+    /// Streams every entry of the active collection as one JSON object per line.
+    pub fn export_jsonl<W: Write>(&self, mut writer: W, include_embeddings: bool) -> anyhow::Result<usize> {
+        let rotxn = self.env_db.read_txn()?;
+        let mut count = 0;
+        for elem in self.heed_db.iter(&rotxn)? {
+            let (id, bytes) = elem?;
+            let entry: DBEntry = serde_json::from_slice(bytes)?;
+            let record = ExportRecord {
+                id,
+                content: entry.content,
+                metadata: entry.metadata,
+                embedding: include_embeddings.then_some(entry.embedding),
+            };
+            serde_json::to_writer(&mut writer, &record)?;
+            writer.write_all(b"\n")?;
+            count += 1;
+        }
+        writer.flush()?;
+        Ok(count)
+    }
+
+    /// Loads records produced by [`SimpleDBNN::export_jsonl`] into the active collection,
+    /// keeping their ids. Existing entries with the same id are replaced. Stored
+    /// embeddings are reused, records without one are embedded again.
+    pub fn import_jsonl<R: BufRead>(&mut self, reader: R) -> anyhow::Result<usize> {
+        let mut records = Vec::with_capacity(IMPORT_BATCH_SIZE);
+        let mut count = 0;
+        for (line_number, line) in reader.lines().enumerate() {
+            let line = line?;
+            if line.trim().is_empty() {
+                continue;
+            }
+            let record: ExportRecord = serde_json::from_str(&line)
+                .map_err(|err| anyhow::anyhow!("Invalid record on line {}: {}", line_number + 1, err))?;
+            if let Some(embedding) = &record.embedding {
+                self.check_embedding(embedding)
+                    .map_err(|err| err.context(format!("Invalid embedding on line {}", line_number + 1)))?;
+            }
+            records.push(record);
+            if records.len() == IMPORT_BATCH_SIZE {
+                count += self.import_records(std::mem::take(&mut records))?;
+            }
+        }
+        if !records.is_empty() {
+            count += self.import_records(records)?;
+        }
+        Ok(count)
+    }
+
+    /// Writes one batch of imported records, embedding those without an embedding together.
+    fn import_records(&mut self, records: Vec<ExportRecord>) -> anyhow::Result<usize> {
+        let missing = records
             .iter()
-            .map(|&elem| {
-                let result = (elem, good_id_to_assign);
-                good_id_to_assign += 1;
-                result
+            .filter(|record| record.embedding.is_none())
+            .map(|record| record.content.as_bytes())
+            .collect::<Vec<&[u8]>>();
+        let mut embeddings = self.embed_all(&missing)?.into_iter();
+        let mut batch = Vec::with_capacity(records.len());
+        for record in records {
+            let embedding = match record.embedding {
+                Some(embedding) => embedding,
+                None => embeddings
+                    .next()
+                    .ok_or_else(|| anyhow::anyhow!("The embedding engine returned too few embeddings"))?,
+            };
+            batch.push((record.id, DBEntry { content: record.content, embedding, metadata: record.metadata }));
+        }
+        self.write_batch(&batch)?;
+        Ok(batch.len())
+    }
+
+    /// Adds the contents to the active collection under consecutive new ids.
+    #[cfg(test)]
+    pub fn put_batch(&mut self, batch: Vec<&str>) -> anyhow::Result<()> {
+        let contents = batch.iter().map(|elem| elem.as_bytes()).collect::<Vec<&[u8]>>();
+        let embeddings = self.embed_all(&contents)?;
+        let batch_with_all = batch
+            .iter()
+            .zip(embeddings)
+            .zip(self.next_id..)
+            .map(|((&elem, embedding), id)| {
+                let entry = DBEntry {
+                    content: elem.to_string(),
+                    embedding,
+                    metadata: Value::Null,
+                };
+                (id, entry)
             })
-            .collect::<Vec<(&str, u32)>>();
-        let embeddings = self.put_batch_nn(batch_with_indexes.as_ref(), index)?;
+            .collect::<Vec<(u32, DBEntry)>>();
 
-        let batch_with_all: Vec<(&str, u32, Vec<f32>)> = batch_with_indexes.iter()
-            .zip(embeddings.iter())
-            .map(|((a, b), c)| (*a, *b, c.clone()))
-            .collect();
+        self.write_batch(batch_with_all.as_ref())
+    }
 
-        self.put_batch_db(batch_with_all.as_ref())?;
-        self.next_id = good_id_to_assign;
-        self.save_backup()?;
-        Ok(())
+    /// Removes the document from both stores. Returns `false` if the id was unknown.
+    pub fn delete(&mut self, id: u32) -> anyhow::Result<bool> {
+        if self.get_db(id)?.is_none() {
+            return Ok(false);
+        }
+        self.delete_db(id)?;
+        self.delete_nn(id, self.index)?;
+        self.clear_journal(&[id])?;
+        Ok(true)
+    }
+
+    /// Replaces the content of an existing document, re-embedding it under the same id.
+    /// Returns `None` if the id was unknown.
+    /// Metadata of the previous version is kept.
+    pub fn update(&mut self, id: u32, content: &str) -> anyhow::Result<Option<Vec<f32>>> {
+        let Some(previous) = self.get_db(id)? else {
+            return Ok(None);
+        };
+        let embedding = self.embed(content)?;
+        self.put_db(content, id, embedding.clone(), previous.metadata)?;
+        // arroy replaces the vector when the item already exists
+        self.add_nn(id, self.index, &embedding)?;
+        self.clear_journal(&[id])?;
+        Ok(Some(embedding))
     }
 
+    /// Writes both environments and the collection registry into one archive file.
+    pub fn snapshot<P: AsRef<Path>>(&mut self, path: P) -> anyhow::Result<SnapshotManifest> {
+        self.flush()?;
+        snapshot::write_snapshot(&self.env_db, &self.env_embedded, self.list_collections()?, path.as_ref())
+    }
+
+    /// Unpacks a snapshot into the paths of `config` and opens it.
+    pub fn restore<P: AsRef<Path>>(archive: P, config: DBConfig<T>) -> anyhow::Result<Self> {
+        snapshot::read_snapshot(archive.as_ref(), &config.db_path, &config.embedded_path)?;
+        Self::from_config(config)
+    }
+
+    #[cfg(test)]
     pub fn clear(&mut self) -> anyhow::Result<()> {
-        let _ = fs::remove_dir_all(&self.path_db.clone());
-        let _ = fs::remove_dir_all(&self.path_embedded.clone());
-        let _ = fs::remove_dir_all(&self.path_config.clone());
-        let _ = fs::remove_file(&self.path_config.clone());
+        let _ = fs::remove_dir_all(&self.path_db);
+        let _ = fs::remove_dir_all(&self.path_embedded);
+        let _ = fs::remove_dir_all(&self.path_config);
+        let _ = fs::remove_file(&self.path_config);
         Ok(())
     }
 }
+impl<T: Embeddable> Drop for SimpleDBNN<T> {
+    fn drop(&mut self) {
+        let _ = self.flush();
+    }
+}
+
+#[cfg(test)]
 pub fn remove(
-    path_buf: &PathBuf,
-    path_embedded: &PathBuf,
-    path_config: &PathBuf,
+    path_buf: &Path,
+    path_embedded: &Path,
+    path_config: &Path,
 ) -> anyhow::Result<()> {
-    let _ = fs::remove_dir_all(path_buf.clone());
-    let _ = fs::remove_dir_all(path_embedded.clone());
-    let _ = fs::remove_dir_all(path_config.clone());
-    let _ = fs::remove_file(path_config.clone());
+    let _ = fs::remove_dir_all(path_buf);
+    let _ = fs::remove_dir_all(path_embedded);
+    let _ = fs::remove_dir_all(path_config);
+    let _ = fs::remove_file(path_config);
     Ok(())
 }
 
 #[cfg(test)]
 pub mod tests {
     use super::*;
-    use crate::*;
-    use arroy::distances::Euclidean;
     use fastembed::TextEmbedding;
+    use crate::services::receipts::{receipt_key, ReceiptStore};
 
     struct FastEmbeddingExample;
 
     impl Embeddable for FastEmbeddingExample {
-        fn to_embedding(&self, content: Vec<u8>) -> Vec<f32> {
-            let model =
-                TextEmbedding::try_new(Default::default()).expect("It can not loaded the model");
+        fn to_embedding(&self, content: &[u8]) -> anyhow::Result<Vec<f32>> {
+            let model = TextEmbedding::try_new(Default::default())?;
             let formatted_content = format!("{:?}", content).clone();
             let documents = vec![formatted_content.as_str()];
-            let embed = model.embed(documents, None).unwrap();
-            embed.to_vec()[0].to_vec()
+            let embed = model.embed(documents, None)?;
+            Ok(embed.to_vec()[0].to_vec())
+        }
+
+        fn dimensions(&self) -> usize {
+            DEFAULT_DIMS
+        }
+
+        fn model_id(&self) -> &str {
+            "Xenova/bge-small-en-v1.5"
         }
     }
 
+    /// `$` contents are far from every other one, `!` contents can not be embedded.
     struct DummyEmbedding;
 
     impl Embeddable for DummyEmbedding {
-        fn to_embedding(&self, content: Vec<u8>) -> Vec<f32> {
-            let content_str = String::from_utf8(content).unwrap();
+        fn to_embedding(&self, content: &[u8]) -> anyhow::Result<Vec<f32>> {
+            let content_str = std::str::from_utf8(content)?;
+            if content_str.starts_with("!") {
+                anyhow::bail!("Can not embed {:?}", content_str);
+            }
             let values: [f32; DEFAULT_DIMS] = if content_str.starts_with("$") {
                 [100.; DEFAULT_DIMS]
             } else {
                 [0.; DEFAULT_DIMS]
             };
-            values.to_vec()
+            Ok(values.to_vec())
+        }
+
+        fn dimensions(&self) -> usize {
+            DEFAULT_DIMS
         }
+
+        fn model_id(&self) -> &str {
+            "dummy"
+        }
+    }
+
+    /// Zero vectors of any size, under any model id.
+    struct ZeroEmbedding {
+        model_id: &'static str,
+        dimensions: usize,
+    }
+
+    impl Embeddable for ZeroEmbedding {
+        fn to_embedding(&self, _: &[u8]) -> anyhow::Result<Vec<f32>> {
+            Ok(vec![0.; self.dimensions])
+        }
+
+        fn dimensions(&self) -> usize {
+            self.dimensions
+        }
+
+        fn model_id(&self) -> &str {
+            self.model_id
+        }
+    }
+
+    /// Opens the store of `dir` with the dummy engine.
+    fn open_db(dir: &std::path::Path) -> SimpleDBNN<DummyEmbedding> {
+        SimpleDBNN::new(dir.join("db"), dir.join("embedded"), dir.join("config"), DummyEmbedding, DEFAULT_DIMS, 0, 46)
+            .unwrap()
     }
+
+    /// A store with the dummy engine in a temporary directory that is deleted
+    /// with the returned guard.
+    fn temp_db() -> (tempfile::TempDir, SimpleDBNN<DummyEmbedding>) {
+        let dir = tempfile::tempdir().unwrap();
+        let db = open_db(dir.path());
+        (dir, db)
+    }
+
     #[test]
     pub fn dummy_test() {
         let db_path = PathBuf::from("test_db");
@@ -453,7 +1414,7 @@ pub mod tests {
         let config_path = PathBuf::from("config");
         let _ = remove(&db_path, &embedded_path, &config_path);
 
-        let mut dummy_db: SimpleDBNN<DummyEmbedding, Euclidean> = SimpleDBNN::new(
+        let mut dummy_db: SimpleDBNN<DummyEmbedding> = SimpleDBNN::new(
             db_path.clone(),
             embedded_path.clone(),
             config_path.clone(),
@@ -465,7 +1426,7 @@ pub mod tests {
             .unwrap();
 
         let content = "Hello, world!";
-        dummy_db.put_db(content, 0,  vec![0.0; 10]).unwrap();
+        dummy_db.put_db(content, 0, vec![0.0; 10], Value::Null).unwrap();
 
         let elems = dummy_db.get_db(0).unwrap().unwrap();
         let restored_content = elems.content;
@@ -480,7 +1441,7 @@ pub mod tests {
         let embedded_path = PathBuf::from("test_embedded_db2");
         let config_path = PathBuf::from("config2");
         let _ = remove(&db_path, &embedded_path, &config_path);
-        let mut dummy_db: SimpleDBNN<DummyEmbedding, Euclidean> = SimpleDBNN::new(
+        let mut dummy_db: SimpleDBNN<DummyEmbedding> = SimpleDBNN::new(
             db_path.clone(),
             embedded_path.clone(),
             config_path.clone(),
@@ -491,7 +1452,7 @@ pub mod tests {
         )
             .unwrap();
         let content = "Hello, world!";
-        dummy_db.put_db(content, 0, vec![0.0; 10]).unwrap();
+        dummy_db.put_db(content, 0, vec![0.0; 10], Value::Null).unwrap();
         let non_found = dummy_db.get_db(1).unwrap();
         assert!(non_found.is_none());
         let _ = remove(&db_path, &embedded_path, &config_path);
@@ -504,7 +1465,7 @@ pub mod tests {
         let config_path = PathBuf::from("config");
         let _ = remove(&db_path, &embedded_path, &config_path);
 
-        let mut dummy_db: SimpleDBNN<DummyEmbedding, Euclidean> = SimpleDBNN::new(
+        let mut dummy_db: SimpleDBNN<DummyEmbedding> = SimpleDBNN::new(
             db_path.clone(),
             embedded_path.clone(),
             config_path.clone(),
@@ -545,7 +1506,7 @@ pub mod tests {
         let config_path = PathBuf::from("config");
         let _ = remove(&db_path.clone(), &embedded_path.clone(), &config_path.clone());
 
-        let mut dummy_db: SimpleDBNN<DummyEmbedding, Euclidean> = SimpleDBNN::new(
+        let mut dummy_db: SimpleDBNN<DummyEmbedding> = SimpleDBNN::new(
             db_path.clone(),
             embedded_path.clone(),
             config_path.clone(),
@@ -562,7 +1523,7 @@ pub mod tests {
         let content3 = "Hello, world3!";
         let content4 = "$$$$$$$$$$$";
         dummy_db
-            .put_batch(vec![content1, content2, content3, content4], 0)
+            .put_batch(vec![content1, content2, content3, content4])
             .unwrap();
 
         let results = dummy_db.get_nn("hello", 0, 4).unwrap();
@@ -575,6 +1536,450 @@ pub mod tests {
         let _ = remove(&db_path, &embedded_path, &config_path);
     }
 
+    #[test]
+    pub fn delete_dummy_test() {
+        let (_dir, mut dummy_db) = temp_db();
+
+        dummy_db.put("Hello, world!").unwrap();
+        dummy_db.put("Hello, world2!").unwrap();
+        dummy_db.put("$$$$$$$$$$$").unwrap();
+
+        assert!(dummy_db.delete(2).unwrap());
+        assert!(!dummy_db.delete(2).unwrap());
+        assert!(dummy_db.get_db(2).unwrap().is_none());
+
+        let results = dummy_db.get_nn("hello", 0, 3).unwrap();
+        assert_eq!(2, results.len());
+        assert!(results.iter().all(|(id, _)| *id != 2));
+    }
+
+    #[test]
+    pub fn update_dummy_test() {
+        let (_dir, mut dummy_db) = temp_db();
+
+        dummy_db.put("Hello, world!").unwrap();
+        dummy_db.put("$$$$$$$$$$$").unwrap();
+
+        let embedding = dummy_db.update(0, "$$$ updated").unwrap().unwrap();
+        assert_eq!(embedding, vec![100.; DEFAULT_DIMS]);
+        assert_eq!("$$$ updated", dummy_db.get_db(0).unwrap().unwrap().content);
+        assert!(dummy_db.update(7, "missing").unwrap().is_none());
+
+        let results = dummy_db.get_nn("$", 0, 2).unwrap();
+        assert_eq!(2, results.len());
+        assert!(results.iter().all(|(_, dist)| *dist < 1.0));
+    }
+
+    #[test]
+    pub fn filtered_dummy_test() {
+        let (_dir, mut dummy_db) = temp_db();
+
+        dummy_db.put_with_metadata("Hello, world!", serde_json::json!({"tenant": "a"})).unwrap();
+        dummy_db.put_with_metadata("Hello, world2!", serde_json::json!({"tenant": "b", "tags": ["x", "y"]})).unwrap();
+        dummy_db.put_with_metadata("$$$$$$$$$$$", serde_json::json!({"tenant": "b"})).unwrap();
+
+        let filter = serde_json::json!({"tenant": "b"});
+        let filter = filter.as_object().unwrap();
+        let results = dummy_db.get_filtered("hello", 3, |entry| entry.matches(filter)).unwrap();
+        assert_eq!(2, results.len());
+        assert_eq!(1, results[0].0);
+        assert_eq!(2, results[1].0);
+
+        let filter = serde_json::json!({"tags": "y"});
+        let filter = filter.as_object().unwrap();
+        let results = dummy_db.get_filtered("hello", 3, |entry| entry.matches(filter)).unwrap();
+        assert_eq!(1, results.len());
+        assert_eq!(1, results[0].0);
+    }
+
+    #[test]
+    pub fn collections_dummy_test() {
+        let (_dir, mut dummy_db) = temp_db();
+
+        dummy_db.put("Hello, world!").unwrap();
+        dummy_db.put("Hello, world2!").unwrap();
+
+        let created = dummy_db.create_collection("team-a", DEFAULT_DIMS, Metric::Euclidean).unwrap().unwrap();
+        assert_eq!(1, created.index);
+        assert!(dummy_db.create_collection("team-a", DEFAULT_DIMS, Metric::Euclidean).unwrap().is_none());
+        assert!(dummy_db.create_collection("bad name", DEFAULT_DIMS, Metric::Euclidean).is_err());
+        assert!(!dummy_db.use_collection("missing").unwrap());
+
+        assert!(dummy_db.use_collection("team-a").unwrap());
+        assert_eq!(0, dummy_db.next_id);
+        dummy_db.put("$$$$$$$$$$$").unwrap();
+        let results = dummy_db.get("hello", 3).unwrap();
+        assert_eq!(1, results.len());
+        assert_eq!("$$$$$$$$$$$", results[0].2.content);
+        // batches land in the index of the active collection
+        dummy_db.put_batch(vec!["$1", "$2"]).unwrap();
+        assert_eq!(3, dummy_db.get("hello", 5).unwrap().len());
+
+        assert!(dummy_db.use_collection(DEFAULT_COLLECTION).unwrap());
+        assert_eq!(2, dummy_db.next_id);
+        assert_eq!(2, dummy_db.get("hello", 3).unwrap().len());
+        assert_eq!(2, dummy_db.list_collections().unwrap().len());
+
+        assert!(dummy_db.drop_collection("team-a").unwrap());
+        assert!(!dummy_db.drop_collection("team-a").unwrap());
+        assert!(dummy_db.drop_collection(DEFAULT_COLLECTION).is_err());
+        assert_eq!(1, dummy_db.list_collections().unwrap().len());
+
+        // dropping deletes the databases, so names can be reused past the database limit
+        for _ in 0..MAX_COLLECTIONS + 1 {
+            dummy_db.create_collection("team-a", DEFAULT_DIMS, Metric::Euclidean).unwrap().unwrap();
+            assert!(dummy_db.drop_collection("team-a").unwrap());
+        }
+        for i in 1..MAX_COLLECTIONS {
+            dummy_db.create_collection(&format!("team-{}", i), DEFAULT_DIMS, Metric::Euclidean).unwrap().unwrap();
+        }
+        assert!(dummy_db.create_collection("one-too-many", DEFAULT_DIMS, Metric::Euclidean).is_err());
+        assert!(dummy_db.use_collection("team-1").unwrap());
+        assert!(dummy_db.get("hello", 3).unwrap().is_empty());
+    }
+
+    #[test]
+    pub fn full_registry_dummy_test() {
+        let dir = tempfile::tempdir().unwrap();
+
+        let mut dummy_db = SimpleDBNN::from_config(DBConfig::with_engine(dir.path(), DummyEmbedding)).unwrap();
+        for i in 1..MAX_COLLECTIONS {
+            dummy_db.create_collection(&format!("team-{}", i), DEFAULT_DIMS, Metric::Euclidean).unwrap().unwrap();
+        }
+        assert!(dummy_db.create_collection("one-too-many", DEFAULT_DIMS, Metric::Euclidean).is_err());
+        drop(dummy_db);
+
+        // every database of a full registry opens again next to the receipt cache
+        let mut dummy_db = SimpleDBNN::from_config(DBConfig::with_engine(dir.path(), DummyEmbedding)).unwrap();
+        let receipts = ReceiptStore::open(&dir.path().join("receipts"), 10, 1024).unwrap();
+        for i in 1..MAX_COLLECTIONS {
+            assert!(dummy_db.use_collection(&format!("team-{}", i)).unwrap());
+        }
+        dummy_db.put("Hello, world!").unwrap();
+        let key = receipt_key([1; 8], &[0; 32]);
+        receipts.put(&key, b"receipt").unwrap();
+        assert_eq!(Some(b"receipt".to_vec()), receipts.get(&key).unwrap());
+    }
+
+    #[test]
+    pub fn model_id_dummy_test() {
+        let dir = tempfile::tempdir().unwrap();
+
+        let mut dummy_db = SimpleDBNN::from_config(DBConfig::with_engine(dir.path(), DummyEmbedding)).unwrap();
+        dummy_db.put("Hello, world!").unwrap();
+        dummy_db.create_collection("team-a", DEFAULT_DIMS, Metric::Euclidean).unwrap().unwrap();
+        let models = dummy_db.list_collections().unwrap().into_iter().map(|c| c.model).collect::<Vec<_>>();
+        assert_eq!(vec![Some("dummy".to_string()); 2], models);
+        drop(dummy_db);
+
+        // vectors of another model or size are never mixed in
+        let other = ZeroEmbedding { model_id: "other", dimensions: DEFAULT_DIMS };
+        assert!(SimpleDBNN::from_config(DBConfig::with_engine(dir.path(), other)).is_err());
+        let larger = ZeroEmbedding { model_id: "dummy", dimensions: DEFAULT_DIMS * 2 };
+        let larger_config = DBConfig { dimensions: DEFAULT_DIMS * 2, ..DBConfig::with_engine(dir.path(), larger) };
+        assert!(SimpleDBNN::from_config(larger_config).is_err());
+        let wrong_dims = DBConfig { dimensions: DEFAULT_DIMS * 2, ..DBConfig::with_engine(dir.path(), DummyEmbedding) };
+        assert!(SimpleDBNN::from_config(wrong_dims).is_err());
+
+        let mut dummy_db = SimpleDBNN::from_config(DBConfig::with_engine(dir.path(), DummyEmbedding)).unwrap();
+        dummy_db.put("Hello, world2!").unwrap();
+        assert_eq!(2, dummy_db.get_collection(DEFAULT_COLLECTION).unwrap().unwrap().next_id);
+    }
+
+    #[test]
+    pub fn embedding_error_dummy_test() {
+        let (_dir, mut dummy_db) = temp_db();
+        dummy_db.put("Hello, world!").unwrap();
+
+        // content the engine fails on is not written
+        let err = dummy_db.put("!bad").unwrap_err();
+        assert!(err.is::<EmbeddingError>());
+        assert!(dummy_db.put_batch(vec!["fine", "!bad"]).is_err());
+        assert_eq!(1, dummy_db.next_id);
+        assert!(dummy_db.get_db(1).unwrap().is_none());
+    }
+
+    #[test]
+    pub fn query_embedding_dummy_test() {
+        let dir = tempfile::tempdir().unwrap();
+        let engine = Arc::new(DummyEmbedding);
+        let mut dummy_db = SimpleDBNN::from_config(DBConfig::with_engine(dir.path(), engine.clone())).unwrap();
+        dummy_db.put("Hello, world!").unwrap();
+        dummy_db.put("$$$$$$$$$$$").unwrap();
+
+        // a query embedded outside of the store finds what the store finds
+        let query = engine.to_query_embedding(b"$").unwrap();
+        let results = dummy_db.get_by_embedding(&query, 1).unwrap();
+        assert_eq!(1, results[0].0);
+        assert_eq!(dummy_db.get("$", 1).unwrap()[0].0, results[0].0);
+        let filtered = dummy_db.get_filtered_by_embedding(&query, 1, |entry| entry.content.starts_with("Hello")).unwrap();
+        assert_eq!(0, filtered[0].0);
+        assert!(dummy_db.get_by_embedding(&[1.], 1).is_err());
+    }
+
+    #[test]
+    pub fn lazy_rebuild_dummy_test() {
+        let dir = tempfile::tempdir().unwrap();
+
+        let mut dummy_db: SimpleDBNN<DummyEmbedding> = SimpleDBNN::from_config(DBConfig {
+            db_path: dir.path().join("db"),
+            embedded_path: dir.path().join("embedded"),
+            config_path: dir.path().join("config"),
+            embed_engine: DummyEmbedding,
+            dimensions: DEFAULT_DIMS,
+            metric: Metric::default(),
+            index: 0,
+            seed: 46,
+            rebuild_threshold: 3,
+            rebuild_interval: None,
+        })
+            .unwrap();
+
+        dummy_db.put("Hello, world!").unwrap();
+        dummy_db.put("Hello, world2!").unwrap();
+        assert_eq!(Some(&2), dummy_db.pending.get(&0));
+        dummy_db.put("$$$$$$$$$$$").unwrap();
+        assert!(dummy_db.pending.is_empty());
+
+        dummy_db.put("Hello, world3!").unwrap();
+        assert_eq!(Some(&1), dummy_db.pending.get(&0));
+        // reads build the buffered items first
+        assert_eq!(4, dummy_db.get_nn("hello", 0, 4).unwrap().len());
+        assert!(dummy_db.pending.is_empty());
+
+        dummy_db.put("Hello, world4!").unwrap();
+        dummy_db.flush().unwrap();
+        assert!(dummy_db.pending.is_empty());
+    }
+
+    #[test]
+    pub fn rebuild_interval_per_index_dummy_test() {
+        let dir = tempfile::tempdir().unwrap();
+        let interval = Duration::from_millis(50);
+        let mut dummy_db = SimpleDBNN::from_config(DBConfig {
+            rebuild_threshold: 100,
+            rebuild_interval: Some(interval),
+            ..DBConfig::with_engine(dir.path(), DummyEmbedding)
+        })
+            .unwrap();
+        dummy_db.create_collection("team-a", DEFAULT_DIMS, Metric::Euclidean).unwrap().unwrap();
+        let team_a = dummy_db.list_collections().unwrap().into_iter().find(|c| c.name == "team-a").unwrap().index;
+
+        dummy_db.put("Hello, world!").unwrap();
+        std::thread::sleep(interval * 2);
+        // the interval of the default index says nothing about the other one
+        assert!(dummy_db.use_collection("team-a").unwrap());
+        dummy_db.put("Hello, world!").unwrap();
+        assert_eq!(Some(&1), dummy_db.pending.get(&team_a));
+        assert_eq!(Some(&1), dummy_db.pending.get(&0));
+
+        // while the default index is built once its own interval elapsed
+        assert!(dummy_db.use_collection(DEFAULT_COLLECTION).unwrap());
+        dummy_db.put("Hello, world2!").unwrap();
+        assert_eq!(None, dummy_db.pending.get(&0));
+        assert_eq!(Some(&1), dummy_db.pending.get(&team_a));
+    }
+
+    #[test]
+    pub fn journal_replay_dummy_test() {
+        let dir = tempfile::tempdir().unwrap();
+
+        {
+            let mut dummy_db = open_db(dir.path());
+            dummy_db.put("Hello, world!").unwrap();
+            // simulate a crash between the heed commit and the arroy commit
+            dummy_db.put_db("$$$$$$$$$$$", 1, vec![100.; DEFAULT_DIMS], Value::Null).unwrap();
+        }
+
+        let mut dummy_db = open_db(dir.path());
+        assert_eq!(2, dummy_db.next_id);
+        let results = dummy_db.get_nn("$", 0, 2).unwrap();
+        assert_eq!(2, results.len());
+        assert_eq!(1, results[0].0);
+    }
+
+    #[test]
+    pub fn drop_replay_dummy_test() {
+        let dir = tempfile::tempdir().unwrap();
+
+        {
+            let mut dummy_db = open_db(dir.path());
+            let created = dummy_db.create_collection("team-a", DEFAULT_DIMS, Metric::Cosine).unwrap().unwrap();
+            assert!(dummy_db.use_collection("team-a").unwrap());
+            dummy_db.put_batch(vec!["$1", "$2"]).unwrap();
+            // simulate a crash between the registry removal and the arroy clear
+            dummy_db.unregister_collection(created).unwrap();
+            assert_eq!(DEFAULT_COLLECTION, dummy_db.collection);
+            // the index of the dropped collection is not handed out again yet
+            assert_eq!(2, dummy_db.create_collection("team-b", DEFAULT_DIMS, Metric::Cosine).unwrap().unwrap().index);
+        }
+
+        let mut dummy_db = open_db(dir.path());
+        let created = dummy_db.create_collection("team-c", DEFAULT_DIMS, Metric::Cosine).unwrap().unwrap();
+        assert_eq!(1, created.index);
+        let reports = dummy_db.check_consistency(false).unwrap();
+        assert!(reports.iter().all(ConsistencyReport::is_consistent));
+    }
+
+    #[test]
+    pub fn consistency_dummy_test() {
+        let (_dir, mut dummy_db) = temp_db();
+
+        dummy_db.put("Hello, world!").unwrap();
+        dummy_db.put_nn("orphan", 5, 0).unwrap();
+        dummy_db.put_db("missing", 1, vec![0.; DEFAULT_DIMS], Value::Null).unwrap();
+
+        let reports = dummy_db.check_consistency(false).unwrap();
+        assert_eq!(1, reports.len());
+        assert_eq!(vec![1], reports[0].missing_vectors);
+        assert_eq!(vec![5], reports[0].orphan_vectors);
+        assert!(!reports[0].stale_corpus_root);
+        assert!(!reports[0].repaired);
+
+        let reports = dummy_db.check_consistency(true).unwrap();
+        assert!(reports[0].repaired);
+        let reports = dummy_db.check_consistency(false).unwrap();
+        assert!(reports[0].is_consistent());
+    }
+
+    #[test]
+    pub fn corpus_root_dummy_test() {
+        let (_dir, mut dummy_db) = temp_db();
+        let empty = dummy_db.corpus_root().unwrap();
+        assert_eq!(hex::encode(docsim_common::merkle::empty_root()), empty.root);
+
+        dummy_db.put("Hello, world!").unwrap();
+        dummy_db.put_batch(vec!["$1", "$2"]).unwrap();
+        let root = dummy_db.corpus_root().unwrap();
+        assert_eq!(3, root.documents);
+        assert_eq!(empty.version + 2, root.version);
+        let leaves = dummy_db
+            .corpus()
+            .unwrap()
+            .iter()
+            .map(|(id, document_digest, embedding)| (*id, leaf_hash(*id, document_digest, embedding)))
+            .collect::<Vec<(u32, Hash)>>();
+        assert_eq!(hex::encode(merkle_root(&leaves)), root.root);
+
+        let proof = dummy_db.inclusion_proof(2).unwrap().unwrap();
+        assert_eq!((root.root.clone(), root.version), (proof.root.clone(), proof.version));
+        let path = proof.path.iter().map(|node| hex::decode(node).unwrap().try_into().unwrap()).collect::<Vec<Hash>>();
+        let root_hash: Hash = hex::decode(&root.root).unwrap().try_into().unwrap();
+        assert!(docsim_common::merkle::verify_inclusion(&root_hash, 2, &leaves[2].1, &path));
+        assert!(dummy_db.inclusion_proof(3).unwrap().is_none());
+
+        dummy_db.delete(2).unwrap();
+        let deleted = dummy_db.corpus_root().unwrap();
+        assert_eq!(hex::encode(merkle_root(&leaves[..2])), deleted.root);
+        assert_eq!(root.version + 1, deleted.version);
+
+        // a tree lost or never built is rebuilt from the documents
+        let mut txn = dummy_db.env_db.write_txn().unwrap();
+        dummy_db.corpus_tree.rebuild(&mut txn, &[]).unwrap();
+        txn.commit().unwrap();
+        let reports = dummy_db.check_consistency(true).unwrap();
+        assert!(reports[0].stale_corpus_root);
+        assert_eq!(deleted.root, dummy_db.corpus_root().unwrap().root);
+    }
+
+    #[test]
+    pub fn snapshot_restore_dummy_test() {
+        let dir = tempfile::tempdir().unwrap();
+        let restored_db_path = dir.path().join("db_restored");
+        let restored_embedded_path = dir.path().join("embedded_db_restored");
+        let archive = dir.path().join("snapshot.zkds");
+
+        let mut dummy_db = open_db(dir.path());
+        dummy_db.put("Hello, world!").unwrap();
+        dummy_db.put("$$$$$$$$$$$").unwrap();
+        let manifest = dummy_db.snapshot(&archive).unwrap();
+        assert_eq!(2, manifest.collections[0].next_id);
+
+        let mut restored: SimpleDBNN<DummyEmbedding> = SimpleDBNN::restore(&archive, DBConfig {
+            db_path: restored_db_path.clone(),
+            embedded_path: restored_embedded_path.clone(),
+            config_path: dir.path().join("config"),
+            embed_engine: DummyEmbedding,
+            dimensions: DEFAULT_DIMS,
+            metric: Metric::default(),
+            index: 0,
+            seed: 46,
+            rebuild_threshold: DEFAULT_REBUILD_THRESHOLD,
+            rebuild_interval: None,
+        })
+            .unwrap();
+        assert_eq!(2, restored.next_id);
+        let results = restored.get("$", 2).unwrap();
+        assert_eq!(2, results.len());
+        assert_eq!("$$$$$$$$$$$", results[0].2.content);
+
+        // restoring over an existing database is refused
+        assert!(snapshot::read_snapshot(&archive, &restored_db_path, &restored_embedded_path).is_err());
+    }
+
+    #[test]
+    pub fn export_import_dummy_test() {
+        let (_dir, mut dummy_db) = temp_db();
+        dummy_db.put("Hello, world!").unwrap();
+        dummy_db.put_with_metadata("$$$$$$$$$$$", serde_json::json!({"tenant": "a"})).unwrap();
+        dummy_db.delete(0).unwrap();
+        assert_eq!(vec![(1, document_digest(1, "$$$$$$$$$$$"), vec![100.; DEFAULT_DIMS])], dummy_db.corpus().unwrap());
+
+        let mut with_embeddings = Vec::new();
+        assert_eq!(1, dummy_db.export_jsonl(&mut with_embeddings, true).unwrap());
+        let mut without_embeddings = Vec::new();
+        dummy_db.export_jsonl(&mut without_embeddings, false).unwrap();
+        let record: ExportRecord = serde_json::from_slice(&without_embeddings).unwrap();
+        assert_eq!(1, record.id);
+        assert!(record.embedding.is_none());
+
+        let (_imported_dir, mut imported) = temp_db();
+        let extra = "\n{\"id\": 4, \"content\": \"Hello, world2!\"}\n";
+        let input = [with_embeddings.as_slice(), extra.as_bytes()].concat();
+        assert_eq!(2, imported.import_jsonl(input.as_slice()).unwrap());
+        assert_eq!(5, imported.next_id);
+
+        let entry = imported.get_db(1).unwrap().unwrap();
+        assert_eq!("$$$$$$$$$$$", entry.content);
+        assert_eq!(serde_json::json!({"tenant": "a"}), entry.metadata);
+        assert_eq!(vec![100.; DEFAULT_DIMS], entry.embedding);
+        let results = imported.get("hello", 2).unwrap();
+        assert_eq!(4, results[0].0);
+
+        let bad_dims = "{\"id\": 9, \"content\": \"x\", \"embedding\": [1.0]}\n";
+        assert!(imported.import_jsonl(bad_dims.as_bytes()).is_err());
+        // 1e39 overflows f32 and parses as infinity
+        let overflow = format!("{{\"id\": 9, \"content\": \"x\", \"embedding\": [{}1e39]}}\n", "1.0, ".repeat(DEFAULT_DIMS - 1));
+        let err = imported.import_jsonl(overflow.as_bytes()).unwrap_err();
+        assert!(err.downcast_ref::<EmbeddingError>().is_some());
+        assert!(imported.get_db(9).unwrap().is_none());
+    }
+
+    #[test]
+    pub fn metric_per_collection_dummy_test() {
+        let (_dir, mut dummy_db) = temp_db();
+        dummy_db.create_collection("cosine", DEFAULT_DIMS, Metric::Cosine).unwrap().unwrap();
+
+        // same direction as the "$" query but far away, and close but in another direction
+        let mut close = vec![0.; DEFAULT_DIMS];
+        close[0] = 100.;
+        let records = [
+            ExportRecord { id: 0, content: "same direction".to_string(), metadata: Value::Null, embedding: Some(vec![1000.; DEFAULT_DIMS]) },
+            ExportRecord { id: 1, content: "close".to_string(), metadata: Value::Null, embedding: Some(close) },
+        ];
+        let jsonl = records.iter().map(|record| serde_json::to_string(record).unwrap() + "\n").collect::<String>();
+
+        dummy_db.import_jsonl(jsonl.as_bytes()).unwrap();
+        assert_eq!(1, dummy_db.get("$", 2).unwrap()[0].0);
+
+        assert!(dummy_db.use_collection("cosine").unwrap());
+        assert_eq!(Metric::Cosine, dummy_db.metric);
+        dummy_db.import_jsonl(jsonl.as_bytes()).unwrap();
+        let results = dummy_db.get("$", 2).unwrap();
+        assert_eq!(0, results[0].0);
+        assert!(results[0].1 < 1e-3);
+    }
+
     #[test]
     pub fn real_batch_dummy_test() {
         let db_path = PathBuf::from("test_dbb");
@@ -582,7 +1987,7 @@ pub mod tests {
         let config_path = PathBuf::from("configb");
         let _ = remove(&db_path, &embedded_path, &config_path);
 
-        let mut dummy_db: SimpleDBNN<FastEmbeddingExample, Euclidean> = SimpleDBNN::new(
+        let mut dummy_db: SimpleDBNN<FastEmbeddingExample> = SimpleDBNN::new(
             db_path.clone(),
             embedded_path.clone(),
             config_path.clone(),
@@ -598,7 +2003,7 @@ pub mod tests {
         let content3 = "Hello, world3!";
         let content4 = "$$$$$$$$$$$";
         dummy_db
-            .put_batch(vec![content1, content2, content3, content4], 0)
+            .put_batch(vec![content1, content2, content3, content4])
             .unwrap();
 
         let results = dummy_db.get("hello", 4).unwrap();
@@ -0,0 +1,100 @@
+use std::fs::{self, File};
+use std::io::{self, BufReader, BufWriter, Read, Write};
+use std::path::Path;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use byteorder::{BigEndian, ReadBytesExt, WriteBytesExt};
+use heed::{CompactionOption, Env};
+use serde::{Deserialize, Serialize};
+
+use crate::services::simple_db_nn::Collection;
+
+/// Archive layout:
+/// `MAGIC | version: u32 | manifest_len: u32 | manifest (json) | db_len: u64 | db | embedded_len: u64 | embedded`
+/// where `db` and `embedded` are compacted copies of the `data.mdb` file of each LMDB environment.
+const MAGIC: &[u8; 8] = b"ZKDSSNAP";
+pub const SNAPSHOT_VERSION: u32 = 1;
+const LMDB_DATA_FILE: &str = "data.mdb";
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct SnapshotManifest {
+    pub version: u32,
+    /// Seconds since the unix epoch.
+    pub created_at: u64,
+    /// Registry at snapshot time, including the id counter of every collection.
+    pub collections: Vec<Collection>,
+}
+
+/// Writes an online copy of both environments into a single archive at `path`.
+/// Each LMDB copy runs in a read transaction of its own, so the caller must keep writes
+/// out until both are taken for heed and arroy to match. The server holds the database
+/// lock for the whole copy, other requests wait for it.
+pub fn write_snapshot(
+    env_db: &Env,
+    env_embedded: &Env,
+    collections: Vec<Collection>,
+    path: &Path,
+) -> anyhow::Result<SnapshotManifest> {
+    let manifest = SnapshotManifest {
+        version: SNAPSHOT_VERSION,
+        created_at: SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs(),
+        collections,
+    };
+
+    let tempdir = tempfile::tempdir()?;
+    let mut db_copy = env_db.copy_to_path(tempdir.path().join("db.mdb"), CompactionOption::Enabled)?;
+    let mut embedded_copy =
+        env_embedded.copy_to_path(tempdir.path().join("embedded.mdb"), CompactionOption::Enabled)?;
+
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    let mut out = BufWriter::new(File::create(path)?);
+    out.write_all(MAGIC)?;
+    out.write_u32::<BigEndian>(SNAPSHOT_VERSION)?;
+    let manifest_bytes = serde_json::to_vec(&manifest)?;
+    out.write_u32::<BigEndian>(manifest_bytes.len() as u32)?;
+    out.write_all(&manifest_bytes)?;
+    for copy in [&mut db_copy, &mut embedded_copy] {
+        out.write_u64::<BigEndian>(copy.metadata()?.len())?;
+        io::copy(copy, &mut out)?;
+    }
+    out.flush()?;
+    Ok(manifest)
+}
+
+/// Unpacks an archive into the two environment directories, which must not hold a database yet.
+pub fn read_snapshot(archive: &Path, db_path: &Path, embedded_path: &Path) -> anyhow::Result<SnapshotManifest> {
+    for dir in [db_path, embedded_path] {
+        if dir.join(LMDB_DATA_FILE).exists() {
+            anyhow::bail!("Refusing to restore over the existing database in {:?}", dir);
+        }
+    }
+
+    let mut input = BufReader::new(File::open(archive)?);
+    let mut magic = [0u8; 8];
+    input.read_exact(&mut magic)?;
+    if &magic != MAGIC {
+        anyhow::bail!("{:?} is not a snapshot archive", archive);
+    }
+    let version = input.read_u32::<BigEndian>()?;
+    if version != SNAPSHOT_VERSION {
+        anyhow::bail!("Unsupported snapshot version {}, expected {}", version, SNAPSHOT_VERSION);
+    }
+    let manifest_len = input.read_u32::<BigEndian>()? as usize;
+    let mut manifest_bytes = vec![0u8; manifest_len];
+    input.read_exact(&mut manifest_bytes)?;
+    let manifest: SnapshotManifest = serde_json::from_slice(&manifest_bytes)?;
+
+    for dir in [db_path, embedded_path] {
+        fs::create_dir_all(dir)?;
+        let len = input.read_u64::<BigEndian>()?;
+        let mut file = File::create(dir.join(LMDB_DATA_FILE))?;
+        let copied = io::copy(&mut (&mut input).take(len), &mut file)?;
+        if copied != len {
+            anyhow::bail!("Truncated snapshot archive {:?}", archive);
+        }
+        file.sync_all()?;
+    }
+    Ok(manifest)
+}
@@ -0,0 +1,129 @@
+//! Integer cosine similarity, evaluated the same way by the fixed-point guest and the host.
+//!
+//! Embeddings are quantized to `i8` on the host and the threshold is a fraction of
+//! [`THRESHOLD_SCALE`]. The guest then only needs integer products and compares squared
+//! quantities, so no `sqrt` or float rounding is involved in what it commits.
+
+use serde::{Deserialize, Serialize};
+
+use crate::journal::{sha256, Digest};
+
+/// Largest magnitude of a quantized component.
+pub const QUANT_MAX: f32 = 127.0;
+
+/// Thresholds are given in units of `1 / THRESHOLD_SCALE`, `0.8` is `8000`.
+pub const THRESHOLD_SCALE: i32 = 10_000;
+
+/// Scales `embedding` so its largest component is ±127 and rounds every component.
+/// Cosine similarity does not depend on the scale, so each vector gets its own.
+pub fn quantize(embedding: &[f32]) -> Vec<i8> {
+    let max = embedding.iter().fold(0f32, |max, value| max.max(value.abs()));
+    if max == 0. || !max.is_finite() {
+        return vec![0; embedding.len()];
+    }
+    let scale = QUANT_MAX / max;
+    embedding
+        .iter()
+        .map(|value| (value * scale).round().clamp(-QUANT_MAX, QUANT_MAX) as i8)
+        .collect()
+}
+
+/// `threshold` in units of `1 / THRESHOLD_SCALE`, rounded to the nearest unit.
+pub fn fixed_threshold(threshold: f32) -> i32 {
+    (threshold * THRESHOLD_SCALE as f32).round() as i32
+}
+
+/// The components as bytes.
+pub fn quantized_bytes(embedding: &[i8]) -> Vec<u8> {
+    embedding.iter().map(|value| *value as u8).collect()
+}
+
+/// The embedding digest the fixed-point guest commits.
+pub fn quantized_digest(embedding: &[i8]) -> Digest {
+    sha256(&quantized_bytes(embedding))
+}
+
+/// The integer terms of the cosine similarity of two quantized embeddings.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub struct FixedSimilarity {
+    pub dot: i64,
+    pub query_norm_sq: i64,
+    pub document_norm_sq: i64,
+}
+
+impl FixedSimilarity {
+    pub fn new(query: &[i8], document: &[i8]) -> Self {
+        let dot = query.iter().zip(document).map(|(a, b)| i64::from(*a) * i64::from(*b)).sum();
+        let norm_sq = |embedding: &[i8]| embedding.iter().map(|a| i64::from(*a) * i64::from(*a)).sum();
+        FixedSimilarity { dot, query_norm_sq: norm_sq(query), document_norm_sq: norm_sq(document) }
+    }
+
+    /// `cosine > threshold / THRESHOLD_SCALE`, decided on integers only:
+    /// `dot * SCALE > threshold * |q| * |d|` compared through the squares of both sides.
+    /// A zero embedding has no similarity and never exceeds any threshold.
+    pub fn exceeds(&self, threshold: i32) -> bool {
+        let norms_sq = i128::from(self.query_norm_sq) * i128::from(self.document_norm_sq);
+        if norms_sq == 0 {
+            return false;
+        }
+        let lhs = i128::from(self.dot) * i128::from(THRESHOLD_SCALE);
+        let threshold = i128::from(threshold);
+        let rhs_sq = threshold * threshold * norms_sq;
+        match (lhs >= 0, threshold >= 0) {
+            (true, true) => lhs * lhs > rhs_sq,
+            (true, false) => true,
+            (false, true) => false,
+            // both sides negative, the larger one is the smaller in magnitude
+            (false, false) => lhs * lhs < rhs_sq,
+        }
+    }
+
+    /// Cosine similarity for display. IEEE division and `sqrt` are correctly rounded,
+    /// so every platform gets the same value from the committed integers.
+    pub fn cosine(&self) -> f64 {
+        let norms_sq = i128::from(self.query_norm_sq) * i128::from(self.document_norm_sq);
+        if norms_sq == 0 {
+            return 0.;
+        }
+        self.dot as f64 / (norms_sq as f64).sqrt()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ranking::cosine_similarity;
+
+    #[test]
+    fn quantize_test() {
+        assert_eq!(vec![127, -64, 0], quantize(&[0.5, -0.25, 0.]));
+        assert_eq!(vec![127, -64, 0], quantize(&[2., -1., 0.]));
+        assert_eq!(vec![0, 0], quantize(&[0., 0.]));
+        assert_eq!(vec![0, 0], quantize(&[f32::INFINITY, 1.]));
+        assert_eq!(8000, fixed_threshold(0.8));
+        assert_eq!(-2500, fixed_threshold(-0.25));
+    }
+
+    #[test]
+    fn fixed_similarity_test() {
+        let query = [0.1, 0.8, 0.3];
+        let document = [0.2, 0.9, 0.1];
+        let fixed = FixedSimilarity::new(&quantize(&query), &quantize(&document));
+        assert!((fixed.cosine() - f64::from(cosine_similarity(&query, &document))).abs() < 0.01);
+
+        // exactly at the threshold is not above it
+        let same = FixedSimilarity::new(&[3, 4], &[3, 4]);
+        assert!(!same.exceeds(THRESHOLD_SCALE));
+        assert!(same.exceeds(THRESHOLD_SCALE - 1));
+        // cosine of (3, 4) and (4, 3) is 0.96
+        let close = FixedSimilarity::new(&[3, 4], &[4, 3]);
+        assert!(close.exceeds(9599));
+        assert!(!close.exceeds(9600));
+        // cosine of (3, 4) and (-4, -3) is -0.96
+        let opposite = FixedSimilarity::new(&[3, 4], &[-4, -3]);
+        assert!(opposite.exceeds(-9601));
+        assert!(!opposite.exceeds(-9600));
+        assert!(!opposite.exceeds(0));
+        assert!(!FixedSimilarity::new(&[0, 0], &[1, 1]).exceeds(-THRESHOLD_SCALE));
+    }
+}
@@ -0,0 +1,140 @@
+//! What every guest commits, decoded with `receipt.journal.decode()` by the host, the
+//! backend and the wasm verifier.
+//!
+//! risc0 serializes a struct as the sequence of its fields, so field order is part of the
+//! journal format. Guests hash with the accelerated zkVM sha256 over the same bytes as
+//! the `*_bytes` functions below, everyone else uses the `*_digest` functions.
+
+use serde::{Deserialize, Serialize};
+use sha2::{Digest as _, Sha256};
+
+use crate::fixed::FixedSimilarity;
+use crate::merkle::Hash;
+use crate::ranking::RankMetric;
+
+pub type Digest = [u8; 32];
+
+pub fn sha256(bytes: &[u8]) -> Digest {
+    Sha256::digest(bytes).into()
+}
+
+/// The little endian bytes of every component.
+pub fn embedding_bytes(embedding: &[f32]) -> Vec<u8> {
+    embedding.iter().flat_map(|value| value.to_le_bytes()).collect()
+}
+
+/// The little endian id followed by the utf-8 content.
+pub fn document_bytes(id: u32, content: &str) -> Vec<u8> {
+    [&id.to_le_bytes()[..], content.as_bytes()].concat()
+}
+
+pub fn embedding_digest(embedding: &[f32]) -> Digest {
+    sha256(&embedding_bytes(embedding))
+}
+
+pub fn document_digest(id: u32, content: &str) -> Digest {
+    sha256(&document_bytes(id, content))
+}
+
+/// Committed by the pair guest.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+pub struct SimilarityJournal {
+    /// `similarity > threshold`
+    pub above_threshold: bool,
+    pub similarity: f32,
+    pub threshold: f32,
+    pub query_digest: Digest,
+    pub embedding_digest: Digest,
+    pub document_digest: Digest,
+}
+
+impl SimilarityJournal {
+    /// Whether the journal was proven for this stored document.
+    pub fn is_for(&self, embedding: &[f32], id: u32, content: &str) -> bool {
+        self.embedding_digest == embedding_digest(embedding) && self.document_digest == document_digest(id, content)
+    }
+}
+
+/// Committed by the fixed-point guest. The digests are over the quantized embeddings.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub struct FixedJournal {
+    pub above_threshold: bool,
+    /// In units of `1 / THRESHOLD_SCALE`.
+    pub threshold: i32,
+    pub similarity: FixedSimilarity,
+    pub query_digest: Digest,
+    pub embedding_digest: Digest,
+    pub document_digest: Digest,
+}
+
+/// Committed by the batch guest, the query digest once and the other digests of
+/// [`SimilarityJournal`] per candidate.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct BatchJournal {
+    pub threshold: f32,
+    pub query_digest: Digest,
+    /// One per candidate, in input order.
+    pub results: Vec<BatchResult>,
+}
+
+/// One candidate of a [`BatchJournal`].
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+pub struct BatchResult {
+    /// `similarity > threshold`
+    pub above_threshold: bool,
+    pub similarity: f32,
+    pub embedding_digest: Digest,
+    pub document_digest: Digest,
+}
+
+impl BatchResult {
+    /// Whether the result was proven for this stored document.
+    pub fn is_for(&self, embedding: &[f32], id: u32, content: &str) -> bool {
+        self.embedding_digest == embedding_digest(embedding) && self.document_digest == document_digest(id, content)
+    }
+}
+
+/// Committed by the ranking guest.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct RankingJournal {
+    /// Merkle root of the whole corpus.
+    pub corpus_root: Hash,
+    pub query_digest: Digest,
+    pub metric: RankMetric,
+    pub k: u32,
+    /// `(id, distance)`, closest first.
+    pub top_k: Vec<(u32, f32)>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn digest_test() {
+        assert_eq!(vec![0, 0, 128, 63, 0, 0, 0, 192], embedding_bytes(&[1., -2.]));
+        assert_eq!(vec![1, 0, 0, 0, b'a'], document_bytes(1, "a"));
+        assert_ne!(document_digest(1, "a"), document_digest(0, "\u{1}a"));
+
+        let journal = SimilarityJournal {
+            above_threshold: true,
+            similarity: 0.9,
+            threshold: 0.8,
+            query_digest: embedding_digest(&[1.]),
+            embedding_digest: embedding_digest(&[2.]),
+            document_digest: document_digest(3, "doc"),
+        };
+        assert!(journal.is_for(&[2.], 3, "doc"));
+        assert!(!journal.is_for(&[1.], 3, "doc"));
+        assert!(!journal.is_for(&[2.], 4, "doc"));
+
+        let result = BatchResult {
+            above_threshold: journal.above_threshold,
+            similarity: journal.similarity,
+            embedding_digest: journal.embedding_digest,
+            document_digest: journal.document_digest,
+        };
+        assert!(result.is_for(&[2.], 3, "doc"));
+        assert!(!result.is_for(&[2.], 3, "other doc"));
+    }
+}
@@ -0,0 +1,6 @@
+//! Code that the guests and the programs checking their journals must agree on.
+
+pub mod fixed;
+pub mod journal;
+pub mod merkle;
+pub mod ranking;
@@ -0,0 +1,201 @@
+use std::collections::BTreeMap;
+use std::convert::Infallible;
+
+use sha2::{Digest, Sha256};
+
+pub type Hash = [u8; 32];
+
+/// Height of the tree. Every `u32` id has its own leaf, so the root does not depend on the
+/// order documents were written in, and a write only rehashes the path of its id.
+pub const DEPTH: u8 = 32;
+
+/// Leaf of an id without a document.
+pub const EMPTY_LEAF: Hash = [0; 32];
+
+const LEAF_PREFIX: u8 = 0;
+const NODE_PREFIX: u8 = 1;
+
+/// `sha256(0x00 | id | document_digest | embedding)`, little endian, where `document_digest` is
+/// the `journal::document_digest` of the content. The prefix keeps leaves and nodes apart.
+pub fn leaf_hash(id: u32, document_digest: &Hash, embedding: &[f32]) -> Hash {
+    let mut hasher = Sha256::new();
+    hasher.update([LEAF_PREFIX]);
+    hasher.update(id.to_le_bytes());
+    hasher.update(document_digest);
+    for value in embedding {
+        hasher.update(value.to_le_bytes());
+    }
+    hasher.finalize().into()
+}
+
+/// `sha256(0x01 | left | right)`
+pub fn node_hash(left: &Hash, right: &Hash) -> Hash {
+    let mut hasher = Sha256::new();
+    hasher.update([NODE_PREFIX]);
+    hasher.update(left);
+    hasher.update(right);
+    hasher.finalize().into()
+}
+
+/// Root of every subtree without documents, indexed by level, leaves being level 0.
+pub fn empty_nodes() -> Vec<Hash> {
+    let mut nodes = vec![EMPTY_LEAF];
+    for level in 0..DEPTH as usize {
+        nodes.push(node_hash(&nodes[level], &nodes[level]));
+    }
+    nodes
+}
+
+/// Root of an empty corpus.
+pub fn empty_root() -> Hash {
+    empty_nodes()[DEPTH as usize]
+}
+
+/// Index at `level` of the node above the leaf of `id`.
+fn node_index(id: u32, level: u8) -> u32 {
+    (u64::from(id) >> level) as u32
+}
+
+/// Root over `(id, leaf)` pairs given in strictly increasing id order.
+pub fn merkle_root(leaves: &[(u32, Hash)]) -> Hash {
+    let empty = empty_nodes();
+    let mut level = leaves.to_vec();
+    for height in 0..DEPTH {
+        let mut parents = Vec::with_capacity(level.len().div_ceil(2));
+        let mut nodes = level.into_iter().peekable();
+        while let Some((index, node)) = nodes.next() {
+            let parent = if index.is_multiple_of(2) {
+                match nodes.next_if(|(next, _)| *next == index + 1) {
+                    Some((_, right)) => node_hash(&node, &right),
+                    None => node_hash(&node, &empty[height as usize]),
+                }
+            } else {
+                node_hash(&empty[height as usize], &node)
+            };
+            parents.push((index >> 1, parent));
+        }
+        level = parents;
+    }
+    level.first().map_or(empty[DEPTH as usize], |(_, root)| *root)
+}
+
+/// Non-empty nodes of a tree, by level and index. Empty nodes are never stored.
+pub trait NodeStore {
+    type Error;
+
+    fn node(&self, level: u8, index: u32) -> Result<Option<Hash>, Self::Error>;
+
+    /// Stores `node`, or removes the entry when it is `None`.
+    fn set_node(&mut self, level: u8, index: u32, node: Option<Hash>) -> Result<(), Self::Error>;
+}
+
+/// An in memory [`NodeStore`].
+impl NodeStore for BTreeMap<(u8, u32), Hash> {
+    type Error = Infallible;
+
+    fn node(&self, level: u8, index: u32) -> Result<Option<Hash>, Infallible> {
+        Ok(self.get(&(level, index)).copied())
+    }
+
+    fn set_node(&mut self, level: u8, index: u32, node: Option<Hash>) -> Result<(), Infallible> {
+        match node {
+            Some(node) => self.insert((level, index), node),
+            None => self.remove(&(level, index)),
+        };
+        Ok(())
+    }
+}
+
+/// Sets the leaf of `id`, `None` for a removed document, and rehashes its path. Returns the new root.
+pub fn set_leaf<S: NodeStore>(store: &mut S, id: u32, leaf: Option<Hash>) -> Result<Hash, S::Error> {
+    let empty = empty_nodes();
+    let mut node = leaf.unwrap_or(EMPTY_LEAF);
+    for level in 0..DEPTH {
+        let index = node_index(id, level);
+        store.set_node(level, index, (node != empty[level as usize]).then_some(node))?;
+        let sibling = store.node(level, index ^ 1)?.unwrap_or(empty[level as usize]);
+        node = if index.is_multiple_of(2) { node_hash(&node, &sibling) } else { node_hash(&sibling, &node) };
+    }
+    store.set_node(DEPTH, 0, (node != empty[DEPTH as usize]).then_some(node))?;
+    Ok(node)
+}
+
+pub fn root<S: NodeStore>(store: &S) -> Result<Hash, S::Error> {
+    Ok(store.node(DEPTH, 0)?.unwrap_or_else(empty_root))
+}
+
+/// Siblings on the path from the leaf of `id` to the root, bottom up.
+pub fn inclusion_path<S: NodeStore>(store: &S, id: u32) -> Result<Vec<Hash>, S::Error> {
+    let empty = empty_nodes();
+    (0..DEPTH)
+        .map(|level| Ok(store.node(level, node_index(id, level) ^ 1)?.unwrap_or(empty[level as usize])))
+        .collect()
+}
+
+/// Root obtained by hashing `leaf` up along `path`.
+pub fn root_from_path(id: u32, leaf: &Hash, path: &[Hash]) -> Hash {
+    path.iter().enumerate().fold(*leaf, |node, (level, sibling)| {
+        if node_index(id, level as u8).is_multiple_of(2) {
+            node_hash(&node, sibling)
+        } else {
+            node_hash(sibling, &node)
+        }
+    })
+}
+
+/// Whether `leaf` is the leaf of `id` in the tree with `root`.
+pub fn verify_inclusion(root: &Hash, id: u32, leaf: &Hash, path: &[Hash]) -> bool {
+    path.len() == DEPTH as usize && root_from_path(id, leaf, path) == *root
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn merkle_root_test() {
+        let leaves = [0, 1, 5, u32::MAX].map(|id| (id, leaf_hash(id, &[1; 32], &[id as f32])));
+        let mut store = BTreeMap::new();
+        assert_eq!(empty_root(), merkle_root(&[]));
+        assert_eq!(empty_root(), root(&store).unwrap());
+
+        for (id, leaf) in leaves {
+            set_leaf(&mut store, id, Some(leaf)).unwrap();
+        }
+        assert_eq!(merkle_root(&leaves), root(&store).unwrap());
+        assert_ne!(leaf_hash(0, &[1; 32], &[1.]), leaf_hash(1, &[1; 32], &[1.]));
+        assert_ne!(leaf_hash(0, &[1; 32], &[1.]), leaf_hash(0, &[2; 32], &[1.]));
+
+        // removing a leaf gives the root of the remaining ones
+        set_leaf(&mut store, 5, None).unwrap();
+        assert_eq!(merkle_root(&[leaves[0], leaves[1], leaves[3]]), root(&store).unwrap());
+        for (id, _) in leaves {
+            set_leaf(&mut store, id, None).unwrap();
+        }
+        assert!(store.is_empty());
+    }
+
+    #[test]
+    fn inclusion_test() {
+        let leaves = [0, 1, 5, u32::MAX].map(|id| (id, leaf_hash(id, &[1; 32], &[id as f32])));
+        let mut store = BTreeMap::new();
+        for (id, leaf) in leaves {
+            set_leaf(&mut store, id, Some(leaf)).unwrap();
+        }
+        let root = root(&store).unwrap();
+
+        for (id, leaf) in leaves {
+            let path = inclusion_path(&store, id).unwrap();
+            assert!(verify_inclusion(&root, id, &leaf, &path));
+            // the same leaf at another position
+            assert!(!verify_inclusion(&root, id ^ 2, &leaf, &path));
+        }
+        let path = inclusion_path(&store, 5).unwrap();
+        assert!(!verify_inclusion(&root, 5, &leaf_hash(5, &[1; 32], &[0.]), &path));
+        // same embedding, other content
+        assert!(!verify_inclusion(&root, 5, &leaf_hash(5, &[2; 32], &[5.]), &path));
+        assert!(!verify_inclusion(&root, 5, &leaves[2].1, &path[1..]));
+        // absent ids have the empty leaf
+        assert!(verify_inclusion(&root, 2, &EMPTY_LEAF, &inclusion_path(&store, 2).unwrap()));
+    }
+}
@@ -0,0 +1,61 @@
+use serde::{Deserialize, Serialize};
+
+/// Metrics the ranking guest can evaluate exactly. Smaller distances rank first.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum RankMetric {
+    Cosine,
+    Euclidean,
+    DotProduct,
+    Manhattan,
+}
+
+impl RankMetric {
+    pub fn distance(&self, a: &[f32], b: &[f32]) -> f32 {
+        match self {
+            RankMetric::Cosine => 1.0 - cosine_similarity(a, b),
+            RankMetric::Euclidean => a.iter().zip(b).map(|(x, y)| (x - y) * (x - y)).sum::<f32>().sqrt(),
+            RankMetric::DotProduct => -a.iter().zip(b).map(|(x, y)| x * y).sum::<f32>(),
+            RankMetric::Manhattan => a.iter().zip(b).map(|(x, y)| (x - y).abs()).sum(),
+        }
+    }
+}
+
+pub fn cosine_similarity(a: &[f32], b: &[f32]) -> f32 {
+    let dot = a.iter().zip(b.iter()).map(|(a, b)| a * b).sum::<f32>();
+    let norm_a = a.iter().map(|x| x * x).sum::<f32>().sqrt();
+    let norm_b = b.iter().map(|x| x * x).sum::<f32>().sqrt();
+    dot / (norm_a * norm_b)
+}
+
+/// Exact top-k over the whole corpus as `(id, distance)`, ties broken by the smaller id.
+/// Distances are compared with `f32::total_cmp`, so the order is deterministic even with NaN.
+pub fn top_k<'a>(
+    query: &[f32],
+    corpus: impl IntoIterator<Item = (u32, &'a [f32])>,
+    k: usize,
+    metric: RankMetric,
+) -> Vec<(u32, f32)> {
+    let mut ranked = corpus
+        .into_iter()
+        .map(|(id, embedding)| (id, metric.distance(query, embedding)))
+        .collect::<Vec<(u32, f32)>>();
+    ranked.sort_by(|a, b| a.1.total_cmp(&b.1).then(a.0.cmp(&b.0)));
+    ranked.truncate(k);
+    ranked
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn top_k_test() {
+        let corpus = [(0, vec![0., 1.]), (1, vec![1., 0.]), (2, vec![1., 0.1]), (3, vec![1., 0.])];
+        let ids = |metric| top_k(&[1., 0.], corpus.iter().map(|(id, embedding)| (*id, embedding.as_slice())), 2, metric).into_iter().map(|(id, _)| id).collect::<Vec<u32>>();
+        assert_eq!(vec![1, 3], ids(RankMetric::Cosine));
+        assert_eq!(vec![1, 3], ids(RankMetric::Euclidean));
+        assert_eq!(vec![1, 2], ids(RankMetric::DotProduct));
+        assert_eq!(vec![1, 3], ids(RankMetric::Manhattan));
+        assert!(top_k(&[1., 0.], std::iter::empty(), 2, RankMetric::Cosine).is_empty());
+    }
+}
@@ -1,20 +1,225 @@
-use risc0_zkvm::{default_prover, ExecutorEnv, ProveInfo};
-use methods::GUEST_CODE_FOR_ZK_PROOF_ELF;
+use risc0_zkvm::{default_prover, ExecutorEnv, ProveInfo, Receipt};
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use docsim_common::fixed::{fixed_threshold, quantize, quantized_digest, FixedSimilarity};
+use docsim_common::merkle::{leaf_hash, merkle_root, Hash};
+use docsim_common::ranking::RankMetric;
+use methods::{
+    GUEST_BATCH_SIMILARITY_ELF, GUEST_BATCH_SIMILARITY_ID, GUEST_CODE_FOR_ZK_PROOF_ELF, GUEST_CODE_FOR_ZK_PROOF_ID,
+    GUEST_FIXED_ELF, GUEST_FIXED_ID, GUEST_RANKING_ELF, GUEST_RANKING_ID,
+};
 
-pub fn execute_prove(embedding1: Vec<f32>, embedding2: Vec<f32>) -> ProveInfo {
-    let env = ExecutorEnv::builder().write(&(embedding1, embedding2)).unwrap().build().unwrap();
+pub use docsim_common::journal::{
+    document_digest, embedding_digest, BatchJournal, BatchResult, Digest, FixedJournal, RankingJournal, SimilarityJournal,
+};
+pub use docsim_common::ranking::cosine_similarity;
+
+/// Id of the guest image the receipts are proven against.
+pub fn image_id() -> [u32; 8] {
+    GUEST_CODE_FOR_ZK_PROOF_ID
+}
+
+/// Id of the guest image that proves a whole top-k in one receipt.
+pub fn batch_image_id() -> [u32; 8] {
+    GUEST_BATCH_SIMILARITY_ID
+}
+
+/// Id of the guest image that proves a top-k is exact over a committed corpus.
+pub fn ranking_image_id() -> [u32; 8] {
+    GUEST_RANKING_ID
+}
+
+/// Id of the guest image that proves integer similarities of quantized embeddings.
+pub fn fixed_image_id() -> [u32; 8] {
+    GUEST_FIXED_ID
+}
+
+/// Threshold of the previously hardcoded guest, used when a search gives none.
+pub const DEFAULT_THRESHOLD: f32 = 0.8;
+
+/// Input of the pair guest, which reads it as `(Vec<f32>, Vec<f32>, u32, String, f32)`.
+#[derive(Serialize, Clone, Debug, PartialEq)]
+pub struct SimilarityInput {
+    pub query: Vec<f32>,
+    pub document: Vec<f32>,
+    pub id: u32,
+    pub content: String,
+    /// The journal result is `similarity > threshold`.
+    pub threshold: f32,
+}
+
+/// Whether the journal was proven for exactly these inputs.
+pub fn journal_matches(journal: &SimilarityJournal, input: &SimilarityInput) -> bool {
+    journal.threshold.to_bits() == input.threshold.to_bits()
+        && journal.query_digest == embedding_digest(&input.query)
+        && journal.is_for(&input.document, input.id, &input.content)
+}
+
+/// Decodes the journal of a bincode receipt, without verifying the receipt.
+pub fn decode_journal<T: DeserializeOwned>(receipt_bytes: &[u8]) -> anyhow::Result<T> {
+    let receipt: Receipt = bincode::deserialize(receipt_bytes)?;
+    Ok(receipt.journal.decode()?)
+}
+
+pub fn execute_prove(input: &SimilarityInput) -> anyhow::Result<ProveInfo> {
+    let env = ExecutorEnv::builder().write(input)?.build()?;
 
     // Obtain the default prover.
     let prover = default_prover();
 
     let prove_info = prover
-        .prove(env, GUEST_CODE_FOR_ZK_PROOF_ELF)
-        .unwrap();
-    prove_info
+        .prove(env, GUEST_CODE_FOR_ZK_PROOF_ELF)?;
+    Ok(prove_info)
+}
+
+pub fn execute_and_serialize_receipt(input: &SimilarityInput) -> anyhow::Result<Vec<u8>> {
+    let prove_info = execute_prove(input)?;
+    let receipt = prove_info.receipt;
+    Ok(bincode::serialize(&receipt)?)
+}
+
+/// One stored document of a [`BatchSimilarityInput`].
+#[derive(Serialize, Clone, Debug, PartialEq)]
+pub struct BatchCandidate {
+    pub embedding: Vec<f32>,
+    pub id: u32,
+    pub content: String,
+}
+
+/// Input of the batch guest, which reads it as `(Vec<f32>, Vec<(Vec<f32>, u32, String)>, f32)`.
+#[derive(Serialize, Clone, Debug, PartialEq)]
+pub struct BatchSimilarityInput {
+    pub query: Vec<f32>,
+    pub candidates: Vec<BatchCandidate>,
+    pub threshold: f32,
+}
+
+/// Whether the batch journal was proven for exactly these inputs.
+pub fn batch_journal_matches(journal: &BatchJournal, input: &BatchSimilarityInput) -> bool {
+    journal.threshold.to_bits() == input.threshold.to_bits()
+        && journal.query_digest == embedding_digest(&input.query)
+        && journal.results.len() == input.candidates.len()
+        && journal
+            .results
+            .iter()
+            .zip(&input.candidates)
+            .all(|(result, candidate)| result.is_for(&candidate.embedding, candidate.id, &candidate.content))
+}
+
+/// Proves the similarity of the query to every candidate in a single execution.
+/// The journal holds the threshold, the query digest and one result per candidate, in the same order.
+pub fn execute_batch_prove(input: &BatchSimilarityInput) -> anyhow::Result<ProveInfo> {
+    let env = ExecutorEnv::builder().write(input)?.build()?;
+
+    let prover = default_prover();
+
+    let prove_info = prover
+        .prove(env, GUEST_BATCH_SIMILARITY_ELF)?;
+    Ok(prove_info)
 }
 
-pub fn execute_and_serialize_receipt(embedding1: Vec<f32>, embedding2: Vec<f32>) -> anyhow::Result<Vec<u8>> {
-    let prove_info = execute_prove(embedding1, embedding2);
+pub fn execute_and_serialize_batch_receipt(input: &BatchSimilarityInput) -> anyhow::Result<Vec<u8>> {
+    let prove_info = execute_batch_prove(input)?;
     let receipt = prove_info.receipt;
     Ok(bincode::serialize(&receipt)?)
-}
\ No newline at end of file
+}
+
+/// Input of the ranking guest, which reads it as `(Vec<f32>, u32, RankMetric, Vec<(u32, Digest, Vec<f32>)>)`.
+/// `corpus` holds `(id, document_digest(id, content), embedding)` sorted by strictly increasing id.
+#[derive(Serialize, Clone, Debug, PartialEq)]
+pub struct RankingInput {
+    pub query: Vec<f32>,
+    pub k: u32,
+    pub metric: RankMetric,
+    pub corpus: Vec<(u32, Digest, Vec<f32>)>,
+}
+
+impl RankingInput {
+    /// Merkle root the guest commits for `corpus`, the root the backend publishes for the collection.
+    pub fn root(&self) -> Hash {
+        let leaves = self
+            .corpus
+            .iter()
+            .map(|(id, document_digest, embedding)| (*id, leaf_hash(*id, document_digest, embedding)))
+            .collect::<Vec<(u32, Hash)>>();
+        merkle_root(&leaves)
+    }
+}
+
+/// Proves that the top-k of `input.query` over the Merkle-committed corpus is exact.
+/// Brute force, so meant for small collections.
+pub fn execute_ranking_prove(input: &RankingInput) -> anyhow::Result<ProveInfo> {
+    let env = ExecutorEnv::builder().write(input)?.build()?;
+
+    let prover = default_prover();
+
+    let prove_info = prover
+        .prove(env, GUEST_RANKING_ELF)?;
+    Ok(prove_info)
+}
+
+pub fn execute_and_serialize_ranking_receipt(input: &RankingInput) -> anyhow::Result<Vec<u8>> {
+    let prove_info = execute_ranking_prove(input)?;
+    let receipt = prove_info.receipt;
+    Ok(bincode::serialize(&receipt)?)
+}
+
+/// Input of the fixed-point guest, which reads it as `(Vec<i8>, Vec<i8>, u32, String, i32)`.
+#[derive(Serialize, Clone, Debug, PartialEq)]
+pub struct FixedSimilarityInput {
+    pub query: Vec<i8>,
+    pub document: Vec<i8>,
+    pub id: u32,
+    pub content: String,
+    /// In units of `1 / THRESHOLD_SCALE`.
+    pub threshold: i32,
+}
+
+impl FixedSimilarityInput {
+    /// Quantizes both embeddings and the threshold.
+    pub fn new(query: &[f32], document: &[f32], id: u32, content: String, threshold: f32) -> Self {
+        FixedSimilarityInput {
+            query: quantize(query),
+            document: quantize(document),
+            id,
+            content,
+            threshold: fixed_threshold(threshold),
+        }
+    }
+
+    /// The similarity the guest commits for this input, computed with the same code.
+    pub fn similarity(&self) -> FixedSimilarity {
+        FixedSimilarity::new(&self.query, &self.document)
+    }
+
+    /// The journal the guest commits for this input.
+    pub fn journal(&self) -> FixedJournal {
+        let similarity = self.similarity();
+        FixedJournal {
+            above_threshold: similarity.exceeds(self.threshold),
+            threshold: self.threshold,
+            similarity,
+            query_digest: quantized_digest(&self.query),
+            embedding_digest: quantized_digest(&self.document),
+            document_digest: document_digest(self.id, &self.content),
+        }
+    }
+}
+
+/// Proves the similarity with integer arithmetic only. Cheaper than [`execute_prove`]
+/// and the journal is exactly [`FixedSimilarityInput::journal`].
+pub fn execute_fixed_prove(input: &FixedSimilarityInput) -> anyhow::Result<ProveInfo> {
+    let env = ExecutorEnv::builder().write(input)?.build()?;
+
+    let prover = default_prover();
+
+    let prove_info = prover
+        .prove(env, GUEST_FIXED_ELF)?;
+    Ok(prove_info)
+}
+
+pub fn execute_and_serialize_fixed_receipt(input: &FixedSimilarityInput) -> anyhow::Result<Vec<u8>> {
+    let prove_info = execute_fixed_prove(input)?;
+    let receipt = prove_info.receipt;
+    Ok(bincode::serialize(&receipt)?)
+}
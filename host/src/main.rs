@@ -2,13 +2,15 @@ use std::fs;
 // These constants represent the RISC-V ELF and the image ID generated by risc0-build.
 // The ELF is used for proving and the ID is used for verification.
 use methods::{
-    GUEST_CODE_FOR_ZK_PROOF_ELF, GUEST_CODE_FOR_ZK_PROOF_ID
+    GUEST_BATCH_SIMILARITY_ID, GUEST_CODE_FOR_ZK_PROOF_ID
 };
-use risc0_zkvm::{ProveInfo, Receipt};
-use risc0_zkvm::{default_prover, ExecutorEnv};
+use risc0_zkvm::Receipt;
 use sha2::{Digest, Sha256};
-use hex;
-use host::execute_prove;
+use host::{
+    batch_journal_matches, execute_batch_prove, execute_fixed_prove, execute_prove, fixed_image_id, journal_matches,
+    BatchCandidate, BatchJournal, BatchSimilarityInput, FixedJournal, FixedSimilarityInput, SimilarityInput,
+    SimilarityJournal, DEFAULT_THRESHOLD,
+};
 
 fn main() {
     // Initialize tracing. In order to view logs, run `RUST_LOG=info cargo run`
@@ -30,9 +32,15 @@ fn main() {
 
     // For example:
     /* add input for circuit */
-    let input: (Vec<f32>, Vec<f32>) = (vec![0.1, 0.8, 0.3], vec![0.2, 0.9, 0.1]);
+    let input = SimilarityInput {
+        query: vec![0.1, 0.8, 0.3],
+        document: vec![0.2, 0.9, 0.1],
+        id: 0,
+        content: "example document".to_string(),
+        threshold: DEFAULT_THRESHOLD,
+    };
 
-    let prove_info = execute_prove(input.0, input.1);
+    let prove_info = execute_prove(&input).unwrap();
 
     // extract the receipt.
     let receipt = prove_info.receipt;
@@ -41,7 +49,7 @@ fn main() {
     //fs::write("receipt.bin", bytes.clone()).unwrap();
     fs::write("web/receipt.bin", bytes.clone()).unwrap();
 
-    let hash = Sha256::digest(&bytes.clone());
+    let hash = Sha256::digest(&bytes);
     println!("SHA256: {}", hex::encode(hash));
 
     println!("receipt={:?}", receipt);
@@ -50,7 +58,8 @@ fn main() {
     let new_receipt: Receipt = bincode::deserialize(bytes.as_slice()).unwrap();
 
     // For example:
-    let _output: u32 = new_receipt.journal.decode().unwrap();
+    let output: SimilarityJournal = new_receipt.journal.decode().unwrap();
+    assert!(journal_matches(&output, &input));
 
 
     // The receipt was verified at the end of proving, but the below code is an
@@ -58,6 +67,33 @@ fn main() {
     new_receipt
         .verify(GUEST_CODE_FOR_ZK_PROOF_ID)
         .unwrap();
+
+    // The batch guest proves a query against several candidates in one receipt.
+    let batch_input = BatchSimilarityInput {
+        query: input.query.clone(),
+        candidates: vec![
+            BatchCandidate { embedding: vec![0.2, 0.9, 0.1], id: 0, content: "example document".to_string() },
+            BatchCandidate { embedding: vec![0.9, 0.1, 0.1], id: 1, content: "other document".to_string() },
+        ],
+        threshold: 0.5,
+    };
+    let batch_info = execute_batch_prove(&batch_input).unwrap();
+    let batch: BatchJournal = batch_info.receipt.journal.decode().unwrap();
+    assert!(batch_journal_matches(&batch, &batch_input));
+    println!("batch image id={:?} threshold={} results={:?}", GUEST_BATCH_SIMILARITY_ID, batch.threshold, batch.results);
+    batch_info.receipt
+        .verify(GUEST_BATCH_SIMILARITY_ID)
+        .unwrap();
+
+    // The fixed-point guest commits exactly what the host computes for the quantized input.
+    let fixed_input = FixedSimilarityInput::new(&input.query, &input.document, input.id, input.content.clone(), DEFAULT_THRESHOLD);
+    let fixed_info = execute_fixed_prove(&fixed_input).unwrap();
+    let fixed_journal: FixedJournal = fixed_info.receipt.journal.decode().unwrap();
+    assert_eq!(fixed_input.journal(), fixed_journal);
+    println!("fixed similarity={} cycles={}", fixed_journal.similarity.cosine(), fixed_info.stats.total_cycles);
+    fixed_info.receipt
+        .verify(fixed_image_id())
+        .unwrap();
 }
 
 
@@ -1,5 +1,3 @@
-use std::fs;
-
 fn main() {
     risc0_build::embed_methods();
 }
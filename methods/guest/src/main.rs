@@ -1,16 +1,27 @@
+use docsim_common::journal::{document_bytes, embedding_bytes, Digest, SimilarityJournal};
+use docsim_common::ranking::cosine_similarity;
 use risc0_zkvm::guest::env;
+use risc0_zkvm::sha::{Impl, Sha256};
 
 
-const THRESHOLD: f32 = 0.8;
+fn digest(bytes: &[u8]) -> Digest {
+    (*Impl::hash_bytes(bytes)).into()
+}
+
 fn main() {
-    let input: (Vec<f32>, Vec<f32>) = env::read();
+    // query embedding, document embedding, document id, document content and threshold
+    let input: (Vec<f32>, Vec<f32>, u32, String, f32) = env::read();
+    let (query, document, id, content, threshold) = input;
 
-    let dot = input.0.iter().zip(input.1.iter()).map(|(a, b)| a * b).sum::<f32>();
-    let norm_a = input.0.iter().map(|x| x * x).sum::<f32>().sqrt();
-    let norm_b = input.1.iter().map(|x| x * x).sum::<f32>().sqrt();
-    let similarity = dot / (norm_a * norm_b);
+    let similarity = cosine_similarity(&query, &document);
 
-    let result = similarity > THRESHOLD;
-    // write public output to the journal
-    env::commit(&(result, similarity));
+    // write public output to the journal, bound to the exact inputs and the threshold applied
+    env::commit(&SimilarityJournal {
+        above_threshold: similarity > threshold,
+        similarity,
+        threshold,
+        query_digest: digest(&embedding_bytes(&query)),
+        embedding_digest: digest(&embedding_bytes(&document)),
+        document_digest: digest(&document_bytes(id, &content)),
+    });
 }
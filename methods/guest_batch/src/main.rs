@@ -0,0 +1,35 @@
+use docsim_common::journal::{document_bytes, embedding_bytes, BatchJournal, BatchResult, Digest};
+use docsim_common::ranking::cosine_similarity;
+use risc0_zkvm::guest::env;
+use risc0_zkvm::sha::{Impl, Sha256};
+
+
+fn digest(bytes: &[u8]) -> Digest {
+    (*Impl::hash_bytes(bytes)).into()
+}
+
+fn main() {
+    // query embedding, (embedding, id, content) of every candidate and the threshold
+    let input: (Vec<f32>, Vec<(Vec<f32>, u32, String)>, f32) = env::read();
+    let (query, candidates, threshold) = input;
+
+    let results = candidates
+        .iter()
+        .map(|(embedding, id, content)| {
+            let similarity = cosine_similarity(&query, embedding);
+            BatchResult {
+                above_threshold: similarity > threshold,
+                similarity,
+                embedding_digest: digest(&embedding_bytes(embedding)),
+                document_digest: digest(&document_bytes(*id, content)),
+            }
+        })
+        .collect::<Vec<BatchResult>>();
+
+    // the threshold applied and the query, then one result per candidate in input order
+    env::commit(&BatchJournal {
+        threshold,
+        query_digest: digest(&embedding_bytes(&query)),
+        results,
+    });
+}
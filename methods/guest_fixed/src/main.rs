@@ -0,0 +1,28 @@
+use docsim_common::fixed::{quantized_bytes, FixedSimilarity};
+use docsim_common::journal::{document_bytes, Digest, FixedJournal};
+use risc0_zkvm::guest::env;
+use risc0_zkvm::sha::{Impl, Sha256};
+
+
+fn digest(bytes: &[u8]) -> Digest {
+    (*Impl::hash_bytes(bytes)).into()
+}
+
+fn main() {
+    // quantized query, quantized document, document id, document content and threshold
+    // in units of 1 / THRESHOLD_SCALE
+    let input: (Vec<i8>, Vec<i8>, u32, String, i32) = env::read();
+    let (query, document, id, content, threshold) = input;
+
+    // integer arithmetic only, so the host reproduces the journal exactly
+    let similarity = FixedSimilarity::new(&query, &document);
+
+    env::commit(&FixedJournal {
+        above_threshold: similarity.exceeds(threshold),
+        threshold,
+        similarity,
+        query_digest: digest(&quantized_bytes(&query)),
+        embedding_digest: digest(&quantized_bytes(&document)),
+        document_digest: digest(&document_bytes(id, &content)),
+    });
+}
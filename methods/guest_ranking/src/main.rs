@@ -0,0 +1,35 @@
+use docsim_common::journal::{embedding_bytes, Digest, RankingJournal};
+use docsim_common::merkle::{leaf_hash, merkle_root, Hash};
+use docsim_common::ranking::{top_k, RankMetric};
+use risc0_zkvm::guest::env;
+use risc0_zkvm::sha::{Impl, Sha256};
+
+
+fn digest(bytes: &[u8]) -> Digest {
+    (*Impl::hash_bytes(bytes)).into()
+}
+
+fn main() {
+    // query embedding, k, metric and the whole corpus as (id, document digest, embedding) in increasing id order
+    let input: (Vec<f32>, u32, RankMetric, Vec<(u32, Digest, Vec<f32>)>) = env::read();
+    let (query, k, metric, corpus) = input;
+
+    // strictly increasing ids, so every document is committed exactly once
+    assert!(corpus.windows(2).all(|pair| pair[0].0 < pair[1].0), "corpus ids must be strictly increasing");
+
+    let leaves = corpus
+        .iter()
+        .map(|(id, document_digest, embedding)| (*id, leaf_hash(*id, document_digest, embedding)))
+        .collect::<Vec<(u32, Hash)>>();
+    let corpus_root = merkle_root(&leaves);
+    let ranked = top_k(&query, corpus.iter().map(|(id, _, embedding)| (*id, embedding.as_slice())), k as usize, metric);
+
+    // the returned ids are the exact top-k of the committed corpus for this query
+    env::commit(&RankingJournal {
+        corpus_root,
+        query_digest: digest(&embedding_bytes(&query)),
+        metric,
+        k,
+        top_k: ranked,
+    });
+}
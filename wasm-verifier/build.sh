@@ -1,5 +1,9 @@
 #!/bin/bash
+set -e
 
 cargo build --release --target wasm32-unknown-unknown
 wasm-pack build --target web
-cp -rf pkg/* ../backend/web/verifier/
\ No newline at end of file
+# both copies must be rebuilt with every change of the exported functions,
+# the backend serves the first one under /verifier
+cp -rf pkg/* ../backend/web/verifier/
+cp -rf pkg/* ../web/verifier/
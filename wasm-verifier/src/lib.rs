@@ -2,17 +2,213 @@ use std::fs;
 use wasm_bindgen::prelude::*;
 use risc0_zkvm::serde::from_slice;
 use risc0_zkvm::Receipt;
+use docsim_common::fixed::{quantize, quantized_digest, THRESHOLD_SCALE};
+use docsim_common::journal::{document_digest, embedding_digest, BatchJournal, FixedJournal, SimilarityJournal};
+use docsim_common::merkle::{self, Hash};
 
-//HARDCODED ZK PROOF ID
-pub const GUEST_CODE_FOR_ZK_PROOF_ID: [u32; 8] = [478837532, 155373028, 3133057801, 3741298655, 2812385851, 1482427873, 3341837043, 1574732756];
+/// Ids of the guests this verifier was built with, as eight u32 words each.
+/// Only with the `methods` feature, otherwise fetch them from `/guest/image-id`.
+#[cfg(feature = "methods")]
+#[wasm_bindgen(getter_with_clone)]
+#[derive(Clone, Debug)]
+pub struct GuestImageIds {
+    pub similarity: Vec<u32>,
+    pub batch_similarity: Vec<u32>,
+    pub fixed_similarity: Vec<u32>,
+    pub ranking: Vec<u32>,
+}
 
+#[cfg(feature = "methods")]
+#[wasm_bindgen]
+pub fn embedded_image_ids() -> GuestImageIds {
+    GuestImageIds {
+        similarity: methods::GUEST_CODE_FOR_ZK_PROOF_ID.to_vec(),
+        batch_similarity: methods::GUEST_BATCH_SIMILARITY_ID.to_vec(),
+        fixed_similarity: methods::GUEST_FIXED_ID.to_vec(),
+        ranking: methods::GUEST_RANKING_ID.to_vec(),
+    }
+}
+
+
+/// The journal of a verified similarity receipt, with hex digests.
+#[wasm_bindgen(getter_with_clone)]
+#[derive(Clone, Debug)]
+pub struct VerifiedJournal {
+    /// `similarity > threshold`
+    pub above_threshold: bool,
+    pub similarity: f32,
+    /// Threshold the guest compared the similarity against.
+    pub threshold: f32,
+    pub query_digest: String,
+    pub embedding_digest: String,
+    pub document_digest: String,
+}
+
+impl From<SimilarityJournal> for VerifiedJournal {
+    fn from(journal: SimilarityJournal) -> Self {
+        VerifiedJournal {
+            above_threshold: journal.above_threshold,
+            similarity: journal.similarity,
+            threshold: journal.threshold,
+            query_digest: hex::encode(journal.query_digest),
+            embedding_digest: hex::encode(journal.embedding_digest),
+            document_digest: hex::encode(journal.document_digest),
+        }
+    }
+}
+
+impl From<FixedJournal> for VerifiedJournal {
+    fn from(journal: FixedJournal) -> Self {
+        VerifiedJournal {
+            above_threshold: journal.above_threshold,
+            similarity: journal.similarity.cosine() as f32,
+            threshold: journal.threshold as f32 / THRESHOLD_SCALE as f32,
+            query_digest: hex::encode(journal.query_digest),
+            embedding_digest: hex::encode(journal.embedding_digest),
+            document_digest: hex::encode(journal.document_digest),
+        }
+    }
+}
+
+/// What a verified batch receipt attests.
+#[wasm_bindgen(getter_with_clone)]
+#[derive(Clone, Debug)]
+pub struct BatchAttestation {
+    pub threshold: f32,
+    /// One similarity per document, in the order they were passed.
+    pub similarities: Vec<f32>,
+}
+
+fn parse_image_id(image_id: &[u32]) -> Result<[u32; 8], JsValue> {
+    image_id
+        .try_into()
+        .map_err(|_| JsValue::from_str("Image id must have 8 words"))
+}
+
+/// Verifies the receipt of a search result and checks that its journal was proven for the
+/// `query_embedding` of the search response and this document embedding, id and content.
+/// Returns the decoded journal.
+/// `image_id` is the `similarity` id published at `/guest/image-id`, as eight u32 words.
+#[wasm_bindgen]
+pub fn verify_receipt(
+    receipt_bytes: &[u8],
+    image_id: &[u32],
+    query_embedding: &[f32],
+    embedding: &[f32],
+    id: u32,
+    content: &str,
+) -> Result<VerifiedJournal, JsValue> {
+    let image_id = parse_image_id(image_id)?;
+    let receipt: Receipt = bincode::deserialize(receipt_bytes)
+        .map_err(|e| JsValue::from_str(&format!("Deserialization error: {e}")))?;
 
+    receipt
+        .verify(image_id)
+        .map_err(|e| JsValue::from_str(&format!("Verification failed: {e}")))?;
+    let journal: SimilarityJournal = receipt
+        .journal
+        .decode()
+        .map_err(|e| JsValue::from_str(&format!("Invalid journal: {e}")))?;
+    if journal.query_digest != embedding_digest(query_embedding) {
+        return Err(JsValue::from_str("Receipt was proven for another query"));
+    }
+    if !journal.is_for(embedding, id, content) {
+        return Err(JsValue::from_str("Receipt was proven for another document or embedding"));
+    }
+    Ok(journal.into())
+}
+
+/// Verifies the receipt of a search result proven with `similarity_mode = "fixed"`. Both
+/// embeddings are quantized here exactly as the backend did, so the journal must hold their digests.
+/// `image_id` is the `fixed_similarity` id published at `/guest/image-id`, as eight u32 words.
 #[wasm_bindgen]
-pub fn verify_receipt(receipt_bytes: &[u8]) -> Result<(), JsValue> {
+pub fn verify_fixed_receipt(
+    receipt_bytes: &[u8],
+    image_id: &[u32],
+    query_embedding: &[f32],
+    embedding: &[f32],
+    id: u32,
+    content: &str,
+) -> Result<VerifiedJournal, JsValue> {
+    let image_id = parse_image_id(image_id)?;
     let receipt: Receipt = bincode::deserialize(receipt_bytes)
         .map_err(|e| JsValue::from_str(&format!("Deserialization error: {e}")))?;
 
     receipt
-        .verify(GUEST_CODE_FOR_ZK_PROOF_ID)
-        .map_err(|e| JsValue::from_str(&format!("Verification failed: {e}")))
-}
\ No newline at end of file
+        .verify(image_id)
+        .map_err(|e| JsValue::from_str(&format!("Verification failed: {e}")))?;
+    let journal: FixedJournal = receipt
+        .journal
+        .decode()
+        .map_err(|e| JsValue::from_str(&format!("Invalid journal: {e}")))?;
+    if journal.query_digest != quantized_digest(&quantize(query_embedding)) {
+        return Err(JsValue::from_str("Receipt was proven for another query"));
+    }
+    if journal.embedding_digest != quantized_digest(&quantize(embedding)) {
+        return Err(JsValue::from_str("Receipt was proven for another embedding"));
+    }
+    if journal.document_digest != document_digest(id, content) {
+        return Err(JsValue::from_str("Receipt was proven for another document"));
+    }
+    Ok(journal.into())
+}
+
+/// Verifies a receipt of the batch guest and checks that it was proven for this query and,
+/// in order, for these documents. `embeddings` holds the document embeddings one after the
+/// other, as JavaScript can not pass nested arrays. Returns the proven threshold and similarities.
+/// `image_id` is the `batch_similarity` id published at `/guest/image-id`, as eight u32 words.
+#[wasm_bindgen]
+pub fn verify_batch_receipt(
+    receipt_bytes: &[u8],
+    image_id: &[u32],
+    query_embedding: &[f32],
+    embeddings: &[f32],
+    ids: &[u32],
+    contents: Vec<String>,
+) -> Result<BatchAttestation, JsValue> {
+    let image_id = parse_image_id(image_id)?;
+    let receipt: Receipt = bincode::deserialize(receipt_bytes)
+        .map_err(|e| JsValue::from_str(&format!("Deserialization error: {e}")))?;
+
+    receipt
+        .verify(image_id)
+        .map_err(|e| JsValue::from_str(&format!("Verification failed: {e}")))?;
+    let journal: BatchJournal = receipt
+        .journal
+        .decode()
+        .map_err(|e| JsValue::from_str(&format!("Invalid journal: {e}")))?;
+    if journal.query_digest != embedding_digest(query_embedding) {
+        return Err(JsValue::from_str("Receipt was proven for another query"));
+    }
+    if journal.results.len() != ids.len() || contents.len() != ids.len() || embeddings.len() % ids.len().max(1) != 0 {
+        return Err(JsValue::from_str("Receipt was proven for another number of documents"));
+    }
+    let dimensions = embeddings.len() / ids.len().max(1);
+    for (i, result) in journal.results.iter().enumerate() {
+        let embedding = &embeddings[i * dimensions..(i + 1) * dimensions];
+        if !result.is_for(embedding, ids[i], &contents[i]) {
+            return Err(JsValue::from_str(&format!("Receipt was proven for another document than {}", ids[i])));
+        }
+    }
+    Ok(BatchAttestation {
+        threshold: journal.threshold,
+        similarities: journal.results.iter().map(|result| result.similarity).collect(),
+    })
+}
+
+fn parse_hash(hex_hash: &str) -> Result<Hash, JsValue> {
+    hex::decode(hex_hash)
+        .ok()
+        .and_then(|bytes| bytes.try_into().ok())
+        .ok_or_else(|| JsValue::from_str(&format!("Not a hex sha256 hash: {hex_hash}")))
+}
+
+/// Checks the `inclusion_proof` of a search result: `true` if the document `id` with this
+/// content and embedding is a leaf of the hex `root` of the proof.
+#[wasm_bindgen]
+pub fn verify_inclusion(root: &str, id: u32, content: &str, embedding: &[f32], path: Vec<String>) -> Result<bool, JsValue> {
+    let root = parse_hash(root)?;
+    let path = path.iter().map(|node| parse_hash(node)).collect::<Result<Vec<Hash>, JsValue>>()?;
+    let leaf = merkle::leaf_hash(id, &document_digest(id, content), embedding);
+    Ok(merkle::verify_inclusion(&root, id, &leaf, &path))
+}
@@ -1,64 +1,167 @@
 /* tslint:disable */
 /* eslint-disable */
-export function verify_receipt(receipt_bytes: Uint8Array, image_id: Uint32Array): void;
+
+/**
+ * What a verified batch receipt attests.
+ */
+export class BatchAttestation {
+    private constructor();
+    free(): void;
+    [Symbol.dispose](): void;
+    /**
+     * One similarity per document, in the order they were passed.
+     */
+    similarities: Float32Array;
+    threshold: number;
+}
+
+/**
+ * The journal of a verified similarity receipt, with hex digests.
+ */
+export class VerifiedJournal {
+    private constructor();
+    free(): void;
+    [Symbol.dispose](): void;
+    /**
+     * `similarity > threshold`
+     */
+    above_threshold: boolean;
+    document_digest: string;
+    embedding_digest: string;
+    query_digest: string;
+    similarity: number;
+    /**
+     * Threshold the guest compared the similarity against.
+     */
+    threshold: number;
+}
+
+/**
+ * Verifies a receipt of the batch guest and checks that it was proven for this query and,
+ * in order, for these documents. `embeddings` holds the document embeddings one after the
+ * other, as JavaScript can not pass nested arrays. Returns the proven threshold and similarities.
+ * `image_id` is the `batch_similarity` id published at `/guest/image-id`, as eight u32 words.
+ */
+export function verify_batch_receipt(receipt_bytes: Uint8Array, image_id: Uint32Array, query_embedding: Float32Array, embeddings: Float32Array, ids: Uint32Array, contents: string[]): BatchAttestation;
+
+/**
+ * Verifies the receipt of a search result proven with `similarity_mode = "fixed"`. Both
+ * embeddings are quantized here exactly as the backend did, so the journal must hold their digests.
+ * `image_id` is the `fixed_similarity` id published at `/guest/image-id`, as eight u32 words.
+ */
+export function verify_fixed_receipt(receipt_bytes: Uint8Array, image_id: Uint32Array, query_embedding: Float32Array, embedding: Float32Array, id: number, content: string): VerifiedJournal;
+
+/**
+ * Checks the `inclusion_proof` of a search result: `true` if the document `id` with this
+ * content and embedding is a leaf of the hex `root` of the proof.
+ */
+export function verify_inclusion(root: string, id: number, content: string, embedding: Float32Array, path: string[]): boolean;
+
+/**
+ * Verifies the receipt of a search result and checks that its journal was proven for the
+ * `query_embedding` of the search response and this document embedding, id and content.
+ * Returns the decoded journal.
+ * `image_id` is the `similarity` id published at `/guest/image-id`, as eight u32 words.
+ */
+export function verify_receipt(receipt_bytes: Uint8Array, image_id: Uint32Array, query_embedding: Float32Array, embedding: Float32Array, id: number, content: string): VerifiedJournal;
 
 export type InitInput = RequestInfo | URL | Response | BufferSource | WebAssembly.Module;
 
 export interface InitOutput {
-  readonly memory: WebAssembly.Memory;
-  readonly verify_receipt: (a: number, b: number, c: number, d: number) => [number, number];
-  readonly sys_read: (a: number, b: number, c: number) => number;
-  readonly sys_panic: (a: number, b: number) => void;
-  readonly sys_verify_integrity: (a: number, b: number) => void;
-  readonly sys_read_words: (a: number, b: number, c: number) => number;
-  readonly sys_pause: (a: number, b: number) => void;
-  readonly sys_halt: (a: number, b: number) => void;
-  readonly sys_rand: (a: number, b: number) => void;
-  readonly syscall_2: (a: number, b: number, c: number, d: number, e: number, f: number) => void;
-  readonly sys_cycle_count: () => bigint;
-  readonly sys_log: (a: number, b: number) => void;
-  readonly sys_input: (a: number) => number;
-  readonly sys_sha_buffer: (a: number, b: number, c: number, d: number) => void;
-  readonly sys_sha_compress: (a: number, b: number, c: number, d: number) => void;
-  readonly sys_bigint: (a: number, b: number, c: number, d: number, e: number) => void;
-  readonly sys_write: (a: number, b: number, c: number) => void;
-  readonly sys_getenv: (a: number, b: number, c: number, d: number) => number;
-  readonly sys_argc: () => number;
-  readonly sys_argv: (a: number, b: number, c: number) => number;
-  readonly sys_alloc_words: (a: number) => number;
-  readonly sys_alloc_aligned: (a: number, b: number) => number;
-  readonly sys_fork: () => number;
-  readonly sys_pipe: (a: number) => number;
-  readonly sys_exit: (a: number) => void;
-  readonly syscall_0: (a: number, b: number, c: number, d: number) => void;
-  readonly syscall_1: (a: number, b: number, c: number, d: number, e: number) => void;
-  readonly syscall_3: (a: number, b: number, c: number, d: number, e: number, f: number, g: number) => void;
-  readonly syscall_4: (a: number, b: number, c: number, d: number, e: number, f: number, g: number, h: number) => void;
-  readonly syscall_5: (a: number, b: number, c: number, d: number, e: number, f: number, g: number, h: number, i: number) => void;
-  readonly sys_verify_integrity2: (a: number, b: number) => void;
-  readonly __wbindgen_export_0: WebAssembly.Table;
-  readonly __wbindgen_malloc: (a: number, b: number) => number;
-  readonly __externref_table_dealloc: (a: number) => void;
-  readonly __wbindgen_start: () => void;
+    readonly memory: WebAssembly.Memory;
+    readonly __wbg_batchattestation_free: (a: number, b: number) => void;
+    readonly __wbg_get_batchattestation_similarities: (a: number) => [number, number];
+    readonly __wbg_get_batchattestation_threshold: (a: number) => number;
+    readonly __wbg_get_verifiedjournal_above_threshold: (a: number) => number;
+    readonly __wbg_get_verifiedjournal_document_digest: (a: number) => [number, number];
+    readonly __wbg_get_verifiedjournal_embedding_digest: (a: number) => [number, number];
+    readonly __wbg_get_verifiedjournal_query_digest: (a: number) => [number, number];
+    readonly __wbg_get_verifiedjournal_similarity: (a: number) => number;
+    readonly __wbg_get_verifiedjournal_threshold: (a: number) => number;
+    readonly __wbg_set_batchattestation_similarities: (a: number, b: number, c: number) => void;
+    readonly __wbg_set_batchattestation_threshold: (a: number, b: number) => void;
+    readonly __wbg_set_verifiedjournal_above_threshold: (a: number, b: number) => void;
+    readonly __wbg_set_verifiedjournal_document_digest: (a: number, b: number, c: number) => void;
+    readonly __wbg_set_verifiedjournal_embedding_digest: (a: number, b: number, c: number) => void;
+    readonly __wbg_set_verifiedjournal_query_digest: (a: number, b: number, c: number) => void;
+    readonly __wbg_set_verifiedjournal_similarity: (a: number, b: number) => void;
+    readonly __wbg_set_verifiedjournal_threshold: (a: number, b: number) => void;
+    readonly __wbg_verifiedjournal_free: (a: number, b: number) => void;
+    readonly verify_batch_receipt: (a: number, b: number, c: number, d: number, e: number, f: number, g: number, h: number, i: number, j: number, k: number, l: number) => [number, number, number];
+    readonly verify_fixed_receipt: (a: number, b: number, c: number, d: number, e: number, f: number, g: number, h: number, i: number, j: number, k: number) => [number, number, number];
+    readonly verify_inclusion: (a: number, b: number, c: number, d: number, e: number, f: number, g: number, h: number, i: number) => [number, number, number];
+    readonly verify_receipt: (a: number, b: number, c: number, d: number, e: number, f: number, g: number, h: number, i: number, j: number, k: number) => [number, number, number];
+    readonly sys_read: (a: number, b: number, c: number) => number;
+    readonly sys_read_words: (a: number, b: number, c: number) => number;
+    readonly sys_verify_integrity: (a: number, b: number) => void;
+    readonly sys_panic: (a: number, b: number) => void;
+    readonly sys_cycle_count: () => bigint;
+    readonly sys_input: (a: number) => number;
+    readonly sys_log: (a: number, b: number) => void;
+    readonly sys_rand: (a: number, b: number) => void;
+    readonly syscall_2: (a: number, b: number, c: number, d: number, e: number, f: number) => void;
+    readonly sys_halt: (a: number, b: number) => void;
+    readonly sys_pause: (a: number, b: number) => void;
+    readonly sys_sha_buffer: (a: number, b: number, c: number, d: number) => void;
+    readonly sys_sha_compress: (a: number, b: number, c: number, d: number) => void;
+    readonly sys_alloc_aligned: (a: number, b: number) => number;
+    readonly sys_alloc_words: (a: number) => number;
+    readonly sys_argc: () => number;
+    readonly sys_argv: (a: number, b: number, c: number) => number;
+    readonly sys_bigint: (a: number, b: number, c: number, d: number, e: number) => void;
+    readonly sys_bigint2_1: (a: number, b: number) => void;
+    readonly sys_bigint2_2: (a: number, b: number, c: number) => void;
+    readonly sys_bigint2_3: (a: number, b: number, c: number, d: number) => void;
+    readonly sys_bigint2_4: (a: number, b: number, c: number, d: number, e: number) => void;
+    readonly sys_bigint2_5: (a: number, b: number, c: number, d: number, e: number, f: number) => void;
+    readonly sys_bigint2_6: (a: number, b: number, c: number, d: number, e: number, f: number, g: number) => void;
+    readonly sys_exit: (a: number) => void;
+    readonly sys_fork: () => number;
+    readonly sys_getenv: (a: number, b: number, c: number, d: number) => number;
+    readonly sys_keccak: (a: number, b: number) => number;
+    readonly sys_pipe: (a: number) => number;
+    readonly sys_poseidon2: (a: number, b: number, c: number, d: number) => void;
+    readonly sys_prove_keccak: (a: number, b: number) => void;
+    readonly sys_write: (a: number, b: number, c: number) => void;
+    readonly syscall_0: (a: number, b: number, c: number, d: number) => void;
+    readonly syscall_0_nr: (a: number, b: number, c: number, d: number, e: number) => void;
+    readonly syscall_1: (a: number, b: number, c: number, d: number, e: number) => void;
+    readonly syscall_1_nr: (a: number, b: number, c: number, d: number, e: number, f: number) => void;
+    readonly syscall_2_nr: (a: number, b: number, c: number, d: number, e: number, f: number, g: number) => void;
+    readonly syscall_3: (a: number, b: number, c: number, d: number, e: number, f: number, g: number) => void;
+    readonly syscall_3_nr: (a: number, b: number, c: number, d: number, e: number, f: number, g: number, h: number) => void;
+    readonly syscall_4: (a: number, b: number, c: number, d: number, e: number, f: number, g: number, h: number) => void;
+    readonly syscall_4_nr: (a: number, b: number, c: number, d: number, e: number, f: number, g: number, h: number, i: number) => void;
+    readonly syscall_5: (a: number, b: number, c: number, d: number, e: number, f: number, g: number, h: number, i: number) => void;
+    readonly syscall_5_nr: (a: number, b: number, c: number, d: number, e: number, f: number, g: number, h: number, i: number, j: number) => void;
+    readonly sys_verify_integrity2: (a: number, b: number) => void;
+    readonly __wbindgen_malloc: (a: number, b: number) => number;
+    readonly __wbindgen_realloc: (a: number, b: number, c: number, d: number) => number;
+    readonly __wbindgen_externrefs: WebAssembly.Table;
+    readonly __wbindgen_free: (a: number, b: number, c: number) => void;
+    readonly __externref_table_alloc: () => number;
+    readonly __externref_table_dealloc: (a: number) => void;
+    readonly __wbindgen_start: () => void;
 }
 
 export type SyncInitInput = BufferSource | WebAssembly.Module;
+
 /**
-* Instantiates the given `module`, which can either be bytes or
-* a precompiled `WebAssembly.Module`.
-*
-* @param {{ module: SyncInitInput }} module - Passing `SyncInitInput` directly is deprecated.
-*
-* @returns {InitOutput}
-*/
+ * Instantiates the given `module`, which can either be bytes or
+ * a precompiled `WebAssembly.Module`.
+ *
+ * @param {{ module: SyncInitInput }} module - Passing `SyncInitInput` directly is deprecated.
+ *
+ * @returns {InitOutput}
+ */
 export function initSync(module: { module: SyncInitInput } | SyncInitInput): InitOutput;
 
 /**
-* If `module_or_path` is {RequestInfo} or {URL}, makes a request and
-* for everything else, calls `WebAssembly.instantiate` directly.
-*
-* @param {{ module_or_path: InitInput | Promise<InitInput> }} module_or_path - Passing `InitInput` directly is deprecated.
-*
-* @returns {Promise<InitOutput>}
-*/
+ * If `module_or_path` is {RequestInfo} or {URL}, makes a request and
+ * for everything else, calls `WebAssembly.instantiate` directly.
+ *
+ * @param {{ module_or_path: InitInput | Promise<InitInput> }} module_or_path - Passing `InitInput` directly is deprecated.
+ *
+ * @returns {Promise<InitOutput>}
+ */
 export default function __wbg_init (module_or_path?: { module_or_path: InitInput | Promise<InitInput> } | InitInput | Promise<InitInput>): Promise<InitOutput>;